@@ -7,6 +7,24 @@ pub trait Aggregate: Default {
     /// Note: This should effectively be a constant value, and should never change.
     fn aggregate_type() -> &'static str;
 
+    /// The version of the schema of the aggregate's snapshots.
+    ///
+    /// Increment this whenever a change to the aggregate means that previously persisted snapshots can no longer be
+    /// loaded as-is, and upgrade snapshots with older schema versions in [Aggregate::upgrade_snapshot]. Defaults to
+    /// zero.
+    fn snapshot_schema_version() -> u32 {
+        0
+    }
+
+    /// Upgrades the serialized payload of a snapshot persisted with an older schema version to the current
+    /// [Aggregate::snapshot_schema_version].
+    ///
+    /// Returns `None` if the snapshot cannot be upgraded, in which case the aggregate is rehydrated from its events
+    /// instead. By default, no snapshots are upgraded.
+    fn upgrade_snapshot(_schema_version: u32, _payload: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+
     /// Consumes the event, applying its effects to the aggregate.
     fn apply<E>(&mut self, event: E)
     where
@@ -92,6 +110,30 @@ pub trait SerializableEvent: Event {
 
     /// Serializes the event to the given buffer.
     fn serialize_event_to_buffer(&self, buffer: &mut Vec<u8>) -> Result<(), Self::Error>;
+
+    /// The schema version of the serialized event, recorded when the event is persisted so that payloads with older
+    /// schema versions can later be transformed by [EventUpcasters](crate::EventUpcasters). Defaults to zero.
+    fn schema_version(&self) -> u32 {
+        0
+    }
+
+    /// Serializes the event to the given buffer in the named payload format, such as `"json"`, returning `false`
+    /// without serializing the event if the format is not supported.
+    ///
+    /// By default, only the `"json"` format is supported, and the event is serialized with
+    /// [SerializableEvent::serialize_event_to_buffer].
+    fn serialize_event_to_buffer_in_format(
+        &self,
+        buffer: &mut Vec<u8>,
+        format: &str,
+    ) -> Result<bool, Self::Error> {
+        if format != "json" {
+            return Ok(false);
+        }
+
+        self.serialize_event_to_buffer(buffer)?;
+        Ok(true)
+    }
 }
 
 /// An event that can be deserialized from a buffer.
@@ -104,4 +146,21 @@ pub trait DeserializableEvent: Event + Sized {
         data: &[u8],
         event_type: &str,
     ) -> Result<Option<Self>, Self::Error>;
+
+    /// Deserializes an event from the provided buffer in the named payload format, such as `"json"`, with prior
+    /// knowledge about the event's type, returning `None` if the format is not supported.
+    ///
+    /// By default, only the `"json"` format is supported, and the event is deserialized with
+    /// [DeserializableEvent::deserialize_event_from_buffer].
+    fn deserialize_event_from_buffer_in_format(
+        data: &[u8],
+        event_type: &str,
+        format: &str,
+    ) -> Result<Option<Self>, Self::Error> {
+        if format != "json" {
+            return Ok(None);
+        }
+
+        Self::deserialize_event_from_buffer(data, event_type)
+    }
 }
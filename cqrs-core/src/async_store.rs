@@ -0,0 +1,200 @@
+//! Asynchronous counterparts to the storage traits.
+//!
+//! Every synchronous store is also usable as an asynchronous store: the blanket implementations in this module
+//! perform the synchronous operation when the future is created and resolve immediately with its result. The
+//! synchronous operation blocks the calling thread, so on an asynchronous executor it also blocks every other task
+//! scheduled on that thread; stores that perform I/O should be run on a thread dedicated to blocking work, such as
+//! one provided by the executor's `spawn_blocking` or equivalent.
+//!
+//! _Requires the `async` feature._
+
+use crate::{
+    aggregate::{Aggregate, AggregateEvent, AggregateId},
+    store::{EventSink, EventSource, SnapshotSink, SnapshotSource},
+    types::{
+        CqrsError, EventNumber, Precondition, Since, Version, VersionedAggregate, VersionedEvent,
+    },
+};
+use std::future::{self, Future};
+
+/// An asynchronous source for reading/loading events.
+pub trait AsyncEventSource<A, E>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+{
+    /// Represents the sequence of events read from the event source.
+    type Events: IntoIterator<Item = VersionedEvent<E>>;
+
+    /// The error type.
+    type Error: CqrsError;
+
+    /// Reads events from the event source for a given identifier.
+    ///
+    /// Only loads events after the event number provided in `since` (See [Since]), and will only load a maximum of
+    /// `max_count` events, if given. If not given, will read all remaining events.
+    fn read_events<I>(
+        &self,
+        id: &I,
+        since: Since,
+        max_count: Option<u64>,
+    ) -> impl Future<Output = Result<Option<Self::Events>, Self::Error>> + Send
+    where
+        I: AggregateId<A>;
+}
+
+/// An asynchronous sink for writing/persisting events with associated metadata.
+pub trait AsyncEventSink<A, E, M>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+{
+    /// The error type.
+    type Error: CqrsError;
+
+    /// Appends events to a given source, with an optional precondition, and associated metadata.
+    ///
+    /// The associated metadata is applied to all events in the append group.
+    fn append_events<I>(
+        &self,
+        id: &I,
+        events: &[E],
+        precondition: Option<Precondition>,
+        metadata: M,
+    ) -> impl Future<Output = Result<EventNumber, Self::Error>> + Send
+    where
+        I: AggregateId<A>;
+}
+
+/// An asynchronous source for reading/loading snapshots of aggregates.
+pub trait AsyncSnapshotSource<A>
+where
+    A: Aggregate,
+{
+    /// The error type.
+    type Error: CqrsError;
+
+    /// Loads a versioned aggregate from the snapshot source.
+    fn get_snapshot<I>(
+        &self,
+        id: &I,
+    ) -> impl Future<Output = Result<Option<VersionedAggregate<A>>, Self::Error>> + Send
+    where
+        I: AggregateId<A>;
+}
+
+/// An asynchronous sink for writing/persisting snapshots of aggregates.
+pub trait AsyncSnapshotSink<A>
+where
+    A: Aggregate,
+{
+    /// The error type.
+    type Error: CqrsError;
+
+    /// Writes an aggregate with its version to the sink. Returns the version number of the latest snapshot.
+    fn persist_snapshot<I>(
+        &self,
+        id: &I,
+        aggregate: &A,
+        version: Version,
+        last_snapshot_version: Option<Version>,
+    ) -> impl Future<Output = Result<Version, Self::Error>> + Send
+    where
+        I: AggregateId<A>;
+}
+
+impl<A, E, T> AsyncEventSource<A, E> for T
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+    T: EventSource<A, E>,
+    T::Events: Send,
+{
+    type Error = <T as EventSource<A, E>>::Error;
+    type Events = <T as EventSource<A, E>>::Events;
+
+    fn read_events<I>(
+        &self,
+        id: &I,
+        since: Since,
+        max_count: Option<u64>,
+    ) -> impl Future<Output = Result<Option<Self::Events>, Self::Error>> + Send
+    where
+        I: AggregateId<A>,
+    {
+        future::ready(EventSource::read_events(self, id, since, max_count))
+    }
+}
+
+impl<A, E, M, T> AsyncEventSink<A, E, M> for T
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+    T: EventSink<A, E, M>,
+{
+    type Error = <T as EventSink<A, E, M>>::Error;
+
+    fn append_events<I>(
+        &self,
+        id: &I,
+        events: &[E],
+        precondition: Option<Precondition>,
+        metadata: M,
+    ) -> impl Future<Output = Result<EventNumber, Self::Error>> + Send
+    where
+        I: AggregateId<A>,
+    {
+        future::ready(EventSink::append_events(
+            self,
+            id,
+            events,
+            precondition,
+            metadata,
+        ))
+    }
+}
+
+impl<A, T> AsyncSnapshotSource<A> for T
+where
+    A: Aggregate + Send,
+    T: SnapshotSource<A>,
+{
+    type Error = <T as SnapshotSource<A>>::Error;
+
+    fn get_snapshot<I>(
+        &self,
+        id: &I,
+    ) -> impl Future<Output = Result<Option<VersionedAggregate<A>>, Self::Error>> + Send
+    where
+        I: AggregateId<A>,
+    {
+        future::ready(SnapshotSource::get_snapshot(self, id))
+    }
+}
+
+impl<A, T> AsyncSnapshotSink<A> for T
+where
+    A: Aggregate,
+    T: SnapshotSink<A>,
+{
+    type Error = <T as SnapshotSink<A>>::Error;
+
+    fn persist_snapshot<I>(
+        &self,
+        id: &I,
+        aggregate: &A,
+        version: Version,
+        last_snapshot_version: Option<Version>,
+    ) -> impl Future<Output = Result<Version, Self::Error>> + Send
+    where
+        I: AggregateId<A>,
+    {
+        future::ready(SnapshotSink::persist_snapshot(
+            self,
+            id,
+            aggregate,
+            version,
+            last_snapshot_version,
+        ))
+    }
+}
@@ -17,9 +17,14 @@
 extern crate void;
 
 mod aggregate;
+#[cfg(feature = "async")]
+pub mod async_store;
+pub mod projection;
 pub mod reactor;
 mod store;
 mod types;
+mod unit_of_work;
+mod upcast;
 
 #[doc(inline)]
 pub use crate::aggregate::{
@@ -28,8 +33,8 @@ pub use crate::aggregate::{
 };
 #[doc(inline)]
 pub use crate::store::{
-    AlwaysSnapshot, EventSink, EventSource, NeverSnapshot, SnapshotSink, SnapshotSource,
-    SnapshotStrategy,
+    AlwaysSnapshot, EventSink, EventSource, EventWithMetadataSource, GlobalEventSource,
+    NeverSnapshot, ReverseEventSource, SnapshotSink, SnapshotSource, SnapshotStrategy,
 };
 #[doc(inline)]
 pub use crate::types::{
@@ -37,3 +42,7 @@ pub use crate::types::{
     SnapshotRecommendation, Version, VersionedAggregate, VersionedEvent,
     VersionedEventWithMetadata,
 };
+#[doc(inline)]
+pub use crate::unit_of_work::{EntityPreconditionFailed, StagedAppend, UnitOfWork, UnitOfWorkSink};
+#[doc(inline)]
+pub use crate::upcast::{EventUpcasters, UpcastError};
@@ -0,0 +1,374 @@
+//! Typed projections of the global event stream.
+
+use crate::{
+    reactor::EventTypesPredicate, Aggregate, AggregateEvent, CqrsError, DeserializableEvent,
+    EventNumber, GlobalEventSource, RawEvent, Since,
+};
+use std::fmt;
+
+/// The default maximum number of events read from an event source in a single batch by a [Projector].
+pub const DEFAULT_BATCH_SIZE: u64 = 100;
+
+/// An event from the global event stream, deserialized for a [Projection].
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct ProjectedEvent<E> {
+    /// The position of the event in the global event stream.
+    pub event_id: EventNumber,
+
+    /// The id of the entity the event belongs to.
+    pub entity_id: String,
+
+    /// The sequence number of the event within the entity's event stream.
+    pub sequence: EventNumber,
+
+    /// The deserialized event.
+    pub event: E,
+}
+
+/// A read model built from the events of a single aggregate type in the global event stream.
+///
+/// A projection is run by a [Projector], which keeps track of its position in the event stream.
+pub trait Projection {
+    /// The aggregate type whose events are projected.
+    type Aggregate: Aggregate;
+
+    /// The event type into which events are deserialized.
+    type Event: AggregateEvent<Self::Aggregate> + DeserializableEvent;
+
+    /// The error type.
+    type Error: CqrsError;
+
+    /// The event types handled by the projection.
+    ///
+    /// Events of other types are skipped without being deserialized.
+    fn event_types(&self) -> EventTypesPredicate {
+        EventTypesPredicate::AllEventTypes
+    }
+
+    /// Applies a single event to the projection.
+    fn project(&mut self, event: ProjectedEvent<Self::Event>) -> Result<(), Self::Error>;
+}
+
+/// An error while running a [Projection] with a [Projector].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProjectionError<S, D, P> {
+    /// An error reading events from the event source.
+    Source(S),
+
+    /// An error deserializing an event.
+    Deserialize(D),
+
+    /// An event handled by the projection which its event type cannot deserialize, such as one whose event type or
+    /// payload format is unknown to it.
+    Unreadable(EventNumber),
+
+    /// An error returned by the projection.
+    Project(P),
+}
+
+impl<S, D, P> fmt::Display for ProjectionError<S, D, P>
+where
+    S: fmt::Display,
+    D: fmt::Display,
+    P: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProjectionError::Source(ref err) => write!(f, "event source error: {}", err),
+            ProjectionError::Deserialize(ref err) => {
+                write!(f, "event deserialization error: {}", err)
+            }
+            ProjectionError::Unreadable(event_id) => write!(f, "unreadable event: {}", event_id),
+            ProjectionError::Project(ref err) => write!(f, "projection error: {}", err),
+        }
+    }
+}
+
+/// The error produced when running projection `P` against event source `S`.
+pub type ProjectorError<S, P> = ProjectionError<
+    <S as GlobalEventSource>::Error,
+    <<P as Projection>::Event as DeserializableEvent>::Error,
+    <P as Projection>::Error,
+>;
+
+/// Runs a [Projection] against any [GlobalEventSource], keeping its own checkpoint.
+///
+/// The position of the projector only advances past an event once the projection has handled it, so an event that
+/// cannot be read or fails to project is read again on the next call to [Projector::catch_up].
+#[derive(Debug)]
+pub struct Projector<P> {
+    projection: P,
+    position: Since,
+    batch_size: u64,
+}
+
+impl<P> Projector<P>
+where
+    P: Projection,
+{
+    /// Constructs a projector that projects events from the beginning of the event stream.
+    pub fn new(projection: P) -> Self {
+        Self::starting_from(projection, Since::BeginningOfStream)
+    }
+
+    /// Constructs a projector that projects events after the given position, such as a previously saved checkpoint.
+    pub fn starting_from(projection: P, position: Since) -> Self {
+        Projector {
+            projection,
+            position,
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+
+    /// Sets the maximum number of events read in a single batch.
+    pub fn with_batch_size(self, batch_size: u64) -> Self {
+        Projector {
+            batch_size: batch_size.max(1),
+            ..self
+        }
+    }
+
+    /// The position of the last event handled by the projector.
+    pub fn position(&self) -> Since {
+        self.position
+    }
+
+    /// Gets the projection.
+    pub fn projection(&self) -> &P {
+        &self.projection
+    }
+
+    /// Consumes the projector, returning the projection.
+    pub fn into_projection(self) -> P {
+        self.projection
+    }
+
+    /// Projects all events in the event source after the current position, returning the number of events projected.
+    pub fn catch_up<S>(&mut self, source: &S) -> Result<usize, ProjectorError<S, P>>
+    where
+        S: GlobalEventSource,
+    {
+        let event_types = self.projection.event_types();
+        let mut count = 0;
+
+        loop {
+            let events = source
+                .read_all_events(self.position, Some(self.batch_size))
+                .map_err(ProjectionError::Source)?;
+
+            let mut read = 0;
+            for event in events {
+                read += 1;
+                if self.project_event::<S>(&event_types, event)? {
+                    count += 1;
+                }
+            }
+
+            if read < self.batch_size {
+                return Ok(count);
+            }
+        }
+    }
+
+    fn project_event<S>(
+        &mut self,
+        event_types: &EventTypesPredicate,
+        event: RawEvent,
+    ) -> Result<bool, ProjectorError<S, P>>
+    where
+        S: GlobalEventSource,
+    {
+        let mut projected = false;
+
+        if event.aggregate_type == P::Aggregate::aggregate_type()
+            && event_types.matches(&event.event_type)
+        {
+            let deserialized = P::Event::deserialize_event_from_buffer_in_format(
+                &event.payload,
+                &event.event_type,
+                &event.payload_format,
+            )
+            .map_err(ProjectionError::Deserialize)?
+            .ok_or(ProjectionError::Unreadable(event.event_id))?;
+
+            self.projection
+                .project(ProjectedEvent {
+                    event_id: event.event_id,
+                    entity_id: event.entity_id,
+                    sequence: event.sequence,
+                    event: deserialized,
+                })
+                .map_err(ProjectionError::Project)?;
+            projected = true;
+        }
+
+        self.position = Since::Event(event.event_id);
+
+        Ok(projected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Event;
+    use void::Void;
+
+    #[derive(Debug, Default)]
+    struct TestAggregate;
+
+    impl Aggregate for TestAggregate {
+        fn aggregate_type() -> &'static str {
+            "test"
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum TestEvent {
+        Created,
+        Renamed,
+    }
+
+    impl Event for TestEvent {
+        fn event_type(&self) -> &'static str {
+            match *self {
+                TestEvent::Created => "created",
+                TestEvent::Renamed => "renamed",
+            }
+        }
+    }
+
+    impl AggregateEvent<TestAggregate> for TestEvent {
+        fn apply_to(self, _aggregate: &mut TestAggregate) {}
+    }
+
+    impl DeserializableEvent for TestEvent {
+        type Error = Void;
+
+        fn deserialize_event_from_buffer(
+            _data: &[u8],
+            event_type: &str,
+        ) -> Result<Option<Self>, Self::Error> {
+            Ok(match event_type {
+                "created" => Some(TestEvent::Created),
+                "renamed" => Some(TestEvent::Renamed),
+                _ => None,
+            })
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct CreatedEntities {
+        entity_ids: Vec<String>,
+    }
+
+    impl Projection for CreatedEntities {
+        type Aggregate = TestAggregate;
+        type Error = Void;
+        type Event = TestEvent;
+
+        fn event_types(&self) -> EventTypesPredicate {
+            EventTypesPredicate::specific(&["created"])
+        }
+
+        fn project(&mut self, event: ProjectedEvent<Self::Event>) -> Result<(), Self::Error> {
+            assert_eq!(TestEvent::Created, event.event);
+            self.entity_ids.push(event.entity_id);
+            Ok(())
+        }
+    }
+
+    struct TestSource(Vec<RawEvent>);
+
+    impl GlobalEventSource for TestSource {
+        type Error = Void;
+        type Events = Vec<RawEvent>;
+
+        fn read_all_events(
+            &self,
+            since: Since,
+            max_count: Option<u64>,
+        ) -> Result<Self::Events, Self::Error> {
+            Ok(self
+                .0
+                .iter()
+                .filter(|event| match since {
+                    Since::BeginningOfStream => true,
+                    Since::Event(event_id) => event.event_id > event_id,
+                })
+                .take(max_count.unwrap_or(u64::max_value()) as usize)
+                .cloned()
+                .collect())
+        }
+    }
+
+    fn raw_event(
+        event_id: u64,
+        aggregate_type: &str,
+        entity_id: &str,
+        event_type: &str,
+    ) -> RawEvent {
+        RawEvent {
+            event_id: EventNumber::new(event_id).unwrap(),
+            aggregate_type: String::from(aggregate_type),
+            entity_id: String::from(entity_id),
+            sequence: EventNumber::MIN_VALUE,
+            event_type: String::from(event_type),
+            payload_format: String::from("json"),
+            payload: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn projects_handled_events_in_batches_and_resumes_from_its_position() {
+        let mut source = TestSource(vec![
+            raw_event(1, "test", "a", "created"),
+            raw_event(2, "other", "b", "created"),
+            raw_event(3, "test", "a", "renamed"),
+            raw_event(4, "test", "c", "created"),
+        ]);
+        let mut projector = Projector::new(CreatedEntities::default()).with_batch_size(2);
+
+        assert_eq!(2, projector.catch_up(&source).unwrap());
+        assert_eq!(
+            Since::Event(EventNumber::new(4).unwrap()),
+            projector.position()
+        );
+
+        source.0.push(raw_event(5, "test", "d", "created"));
+        assert_eq!(1, projector.catch_up(&source).unwrap());
+        assert_eq!(0, projector.catch_up(&source).unwrap());
+
+        assert_eq!(vec!["a", "c", "d"], projector.into_projection().entity_ids);
+    }
+
+    #[test]
+    fn starts_from_a_saved_position() {
+        let source = TestSource(vec![
+            raw_event(1, "test", "a", "created"),
+            raw_event(2, "test", "b", "created"),
+        ]);
+        let mut projector = Projector::starting_from(
+            CreatedEntities::default(),
+            Since::Event(EventNumber::MIN_VALUE),
+        );
+
+        assert_eq!(1, projector.catch_up(&source).unwrap());
+        assert_eq!(vec!["b"], projector.projection().entity_ids);
+    }
+
+    #[test]
+    fn stops_at_events_it_cannot_read_in_their_payload_format() {
+        let mut unreadable = raw_event(2, "test", "b", "created");
+        unreadable.payload_format = String::from("msgpack");
+        let source = TestSource(vec![raw_event(1, "test", "a", "created"), unreadable]);
+        let mut projector = Projector::new(CreatedEntities::default());
+
+        assert_eq!(
+            ProjectionError::Unreadable(EventNumber::new(2).unwrap()),
+            projector.catch_up(&source).unwrap_err()
+        );
+        assert_eq!(Since::Event(EventNumber::MIN_VALUE), projector.position());
+        assert_eq!(vec!["a"], projector.projection().entity_ids);
+    }
+}
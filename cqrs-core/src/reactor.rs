@@ -1,9 +1,16 @@
+//! Types for reacting to events as they are appended to an event store.
+
 use crate::{CqrsError, RawEvent};
+use std::fmt;
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+/// Filters events by aggregate type and event type.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub enum AggregatePredicate {
+    /// Matches events from any aggregate type with the given event types.
     AllAggregates(EventTypesPredicate),
-    SpecificAggregates(&'static [SpecificAggregatePredicate]),
+
+    /// Matches events matching any of the given aggregate-specific predicates.
+    SpecificAggregates(Vec<SpecificAggregatePredicate>),
 }
 
 impl Default for AggregatePredicate {
@@ -12,10 +19,40 @@ impl Default for AggregatePredicate {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+/// Filters events by event type.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub enum EventTypesPredicate {
+    /// Matches events of any type.
     AllEventTypes,
-    SpecificEventTypes(&'static [&'static str]),
+
+    /// Matches events of the given types.
+    SpecificEventTypes(Vec<String>),
+}
+
+impl EventTypesPredicate {
+    /// Constructs a predicate matching events of the given types.
+    pub fn specific<I>(event_types: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        EventTypesPredicate::SpecificEventTypes(
+            event_types
+                .into_iter()
+                .map(|event_type| event_type.as_ref().to_owned())
+                .collect(),
+        )
+    }
+
+    /// Whether the predicate matches the given event type.
+    pub fn matches(&self, event_type: &str) -> bool {
+        match *self {
+            EventTypesPredicate::AllEventTypes => true,
+            EventTypesPredicate::SpecificEventTypes(ref event_types) => {
+                event_types.iter().any(|t| t == event_type)
+            }
+        }
+    }
 }
 
 impl Default for EventTypesPredicate {
@@ -24,29 +61,332 @@ impl Default for EventTypesPredicate {
     }
 }
 
-#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+/// Filters events by entity id.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum EntityIdPredicate {
+    /// Matches events for any entity.
+    AllEntities,
+
+    /// Matches events for the given entities.
+    SpecificEntities(Vec<String>),
+
+    /// Matches events for entities whose id matches a SQL `LIKE` pattern, where `%` matches any sequence of
+    /// characters, `_` matches any single character, and `\` escapes the character following it.
+    MatchingPattern(String),
+}
+
+impl EntityIdPredicate {
+    /// Whether the predicate matches the given entity id.
+    pub fn matches(&self, entity_id: &str) -> bool {
+        match *self {
+            EntityIdPredicate::AllEntities => true,
+            EntityIdPredicate::SpecificEntities(ref entity_ids) => {
+                entity_ids.iter().any(|id| id == entity_id)
+            }
+            EntityIdPredicate::MatchingPattern(ref pattern) => {
+                let pattern: Vec<char> = pattern.chars().collect();
+                let entity_id: Vec<char> = entity_id.chars().collect();
+                matches_like_pattern(&pattern, &entity_id)
+            }
+        }
+    }
+}
+
+impl Default for EntityIdPredicate {
+    fn default() -> Self {
+        EntityIdPredicate::AllEntities
+    }
+}
+
+fn matches_like_pattern(pattern: &[char], value: &[char]) -> bool {
+    match pattern.split_first() {
+        None => value.is_empty(),
+        Some((&'%', rest)) => {
+            (0..=value.len()).any(|skip| matches_like_pattern(rest, &value[skip..]))
+        }
+        Some((&'_', rest)) => !value.is_empty() && matches_like_pattern(rest, &value[1..]),
+        Some((&'\\', rest)) if !rest.is_empty() => {
+            value.first() == rest.first() && matches_like_pattern(&rest[1..], &value[1..])
+        }
+        Some((c, rest)) => value.first() == Some(c) && matches_like_pattern(rest, &value[1..]),
+    }
+}
+
+/// Filters events by the text value of a top-level field of their metadata.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct MetadataPredicate {
+    /// The name of the metadata field, such as `initiated_by`.
+    pub field: String,
+
+    /// The value the field must have.
+    pub value: String,
+}
+
+/// Filters the events fed to a reaction.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
 pub struct ReactionPredicate {
+    /// Filters events by aggregate type and event type.
     pub aggregate_predicate: AggregatePredicate,
+
+    /// Filters events by entity id.
+    pub entity_id_predicate: EntityIdPredicate,
+
+    /// Filters events by metadata. An event must match all of these predicates.
+    pub metadata_predicates: Vec<MetadataPredicate>,
+}
+
+impl ReactionPredicate {
+    /// Constructs a predicate matching events by aggregate type and event type.
+    pub fn new(aggregate_predicate: AggregatePredicate) -> Self {
+        ReactionPredicate {
+            aggregate_predicate,
+            ..ReactionPredicate::default()
+        }
+    }
+
+    /// Only matches events whose entity id matches `entity_id_predicate`.
+    pub fn with_entity_ids(self, entity_id_predicate: EntityIdPredicate) -> Self {
+        ReactionPredicate {
+            entity_id_predicate,
+            ..self
+        }
+    }
+
+    /// Only matches events whose metadata has the given text value for `field`.
+    pub fn with_metadata(mut self, field: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata_predicates.push(MetadataPredicate {
+            field: field.into(),
+            value: value.into(),
+        });
+        self
+    }
+}
+
+/// A [ReactionPredicate] which has been checked to be matchable against raw events.
+///
+/// Raw events do not carry their metadata, so predicates with metadata predicates can only be used with event stores
+/// that filter events themselves, such as PostgreSQL.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct RawEventPredicate {
+    predicate: ReactionPredicate,
+}
+
+impl RawEventPredicate {
+    /// Checks that `predicate` can be matched against raw events.
+    ///
+    /// Returns an error if the predicate has any metadata predicates.
+    pub fn new(predicate: ReactionPredicate) -> Result<Self, MetadataPredicatesUnsupported> {
+        if !predicate.metadata_predicates.is_empty() {
+            return Err(MetadataPredicatesUnsupported);
+        }
+
+        Ok(RawEventPredicate { predicate })
+    }
+
+    /// Gets the checked predicate.
+    pub fn predicate(&self) -> &ReactionPredicate {
+        &self.predicate
+    }
+
+    /// Whether the predicate matches the given event.
+    pub fn matches(&self, event: &RawEvent) -> bool {
+        let aggregate_matches = match self.predicate.aggregate_predicate {
+            AggregatePredicate::AllAggregates(ref event_types) => {
+                event_types.matches(&event.event_type)
+            }
+            AggregatePredicate::SpecificAggregates(ref aggregate_predicates) => {
+                aggregate_predicates.iter().any(|predicate| {
+                    predicate.aggregate_type == event.aggregate_type
+                        && predicate.event_types.matches(&event.event_type)
+                })
+            }
+        };
+
+        aggregate_matches && self.predicate.entity_id_predicate.matches(&event.entity_id)
+    }
+}
+
+/// An error indicating that a [ReactionPredicate] with metadata predicates cannot be matched against raw events, which
+/// do not carry their metadata.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MetadataPredicatesUnsupported;
+
+impl fmt::Display for MetadataPredicatesUnsupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("metadata predicates cannot be matched against raw events")
+    }
 }
 
-#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+/// Filters events of a specific aggregate type by event type.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
 pub struct SpecificAggregatePredicate {
-    pub aggregate_type: &'static str,
+    /// The aggregate type.
+    pub aggregate_type: String,
+
+    /// Filters the aggregate's events by event type.
     pub event_types: EventTypesPredicate,
 }
 
+impl SpecificAggregatePredicate {
+    /// Constructs a predicate matching events of an aggregate type by event type.
+    pub fn new(aggregate_type: impl Into<String>, event_types: EventTypesPredicate) -> Self {
+        SpecificAggregatePredicate {
+            aggregate_type: aggregate_type.into(),
+            event_types,
+        }
+    }
+}
+
 /// A Reactor “reacts” to events, as they are created.
 pub trait Reactor {
-    fn start_reaction<R: Reaction>(reaction: R);
-    fn stop_reaction();
+    /// The error type for errors raised by the reactor itself, rather than by a reaction.
+    type Error: CqrsError;
+
+    /// Feeds events to the reaction until [Reactor::stop_reaction] is called, returning the number of events reacted
+    /// to.
+    fn start_reaction<R: Reaction>(
+        &self,
+        reaction: R,
+    ) -> Result<usize, ReactionError<Self::Error, R::Error>>;
+
+    /// Signals any running reactions to stop.
+    fn stop_reaction(&self);
+}
+
+/// An error while running a reaction with a [Reactor].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ReactionError<R, E> {
+    /// An error raised by the reactor.
+    Reactor(R),
+
+    /// An error returned by the reaction.
+    React(E),
+
+    /// The reaction's predicate cannot be used by the reactor.
+    UnsupportedPredicate(MetadataPredicatesUnsupported),
+}
+
+impl<R, E> fmt::Display for ReactionError<R, E>
+where
+    R: fmt::Display,
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReactionError::Reactor(ref err) => write!(f, "reactor error: {}", err),
+            ReactionError::React(ref err) => write!(f, "reaction error: {}", err),
+            ReactionError::UnsupportedPredicate(ref err) => {
+                write!(f, "unsupported predicate: {}", err)
+            }
+        }
+    }
 }
 
 /// A Reaction is stateless, triggering side-effects in response to an event's creation.
 pub trait Reaction {
+    /// The error type.
     type Error: CqrsError;
 
+    /// The name under which the reaction's progress is recorded.
     fn reaction_name() -> &'static str;
+
+    /// Reacts to an event.
     fn react(&mut self, event: RawEvent) -> Result<(), Self::Error>;
+
+    /// The events to react to.
     fn predicate(&self) -> ReactionPredicate;
+
+    /// The maximum amount of time to wait between batches once all events have been reacted to.
     fn interval() -> std::time::Duration;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EventNumber;
+
+    fn matches_raw(predicate: ReactionPredicate, event: &RawEvent) -> bool {
+        RawEventPredicate::new(predicate).unwrap().matches(event)
+    }
+
+    #[test]
+    fn predicate_matches_events_by_aggregate_and_event_type() {
+        let event = RawEvent {
+            event_id: EventNumber::MIN_VALUE,
+            aggregate_type: String::from("test"),
+            entity_id: String::from("a"),
+            sequence: EventNumber::MIN_VALUE,
+            event_type: String::from("test"),
+            payload_format: String::from("json"),
+            payload: Vec::new(),
+        };
+
+        assert!(matches_raw(ReactionPredicate::default(), &event));
+        assert!(matches_raw(
+            ReactionPredicate::new(AggregatePredicate::SpecificAggregates(vec![
+                SpecificAggregatePredicate::new(
+                    "test",
+                    EventTypesPredicate::specific(&["other", "test"])
+                ),
+            ])),
+            &event
+        ));
+        assert!(!matches_raw(
+            ReactionPredicate::new(AggregatePredicate::AllAggregates(
+                EventTypesPredicate::specific(vec!["other"])
+            )),
+            &event
+        ));
+    }
+
+    #[test]
+    fn predicate_matches_events_by_entity_id() {
+        let event = RawEvent {
+            event_id: EventNumber::MIN_VALUE,
+            aggregate_type: String::from("test"),
+            entity_id: String::from("order_42"),
+            sequence: EventNumber::MIN_VALUE,
+            event_type: String::from("test"),
+            payload_format: String::from("json"),
+            payload: Vec::new(),
+        };
+        let matches = |entity_id_predicate| {
+            matches_raw(
+                ReactionPredicate::default().with_entity_ids(entity_id_predicate),
+                &event,
+            )
+        };
+
+        assert!(matches(EntityIdPredicate::SpecificEntities(vec![
+            String::from("order_42")
+        ])));
+        assert!(!matches(EntityIdPredicate::SpecificEntities(vec![
+            String::from("order_4")
+        ])));
+        assert!(matches(EntityIdPredicate::MatchingPattern(String::from(
+            "order%"
+        ))));
+        assert!(matches(EntityIdPredicate::MatchingPattern(String::from(
+            "%_4_"
+        ))));
+        assert!(matches(EntityIdPredicate::MatchingPattern(String::from(
+            "order\\_%"
+        ))));
+        assert!(!matches(EntityIdPredicate::MatchingPattern(String::from(
+            "order\\%"
+        ))));
+        assert!(!matches(EntityIdPredicate::MatchingPattern(String::from(
+            "%_4"
+        ))));
+    }
+
+    #[test]
+    fn predicate_with_metadata_cannot_match_raw_events() {
+        assert_eq!(
+            Err(MetadataPredicatesUnsupported),
+            RawEventPredicate::new(
+                ReactionPredicate::default().with_metadata("initiated_by", "admin")
+            )
+        );
+    }
+}
@@ -1,10 +1,11 @@
 use crate::{
     aggregate::{Aggregate, AggregateEvent, AggregateId},
     types::{
-        CqrsError, EventNumber, Precondition, Since, SnapshotRecommendation, Version,
-        VersionedAggregate, VersionedEvent,
+        Before, CqrsError, EventNumber, Precondition, RawEvent, Since, SnapshotRecommendation,
+        Version, VersionedAggregate, VersionedEvent, VersionedEventWithMetadata,
     },
 };
+use std::sync::Arc;
 
 /// A source for reading/loading events.
 pub trait EventSource<A, E>
@@ -32,6 +33,105 @@ where
         I: AggregateId<A>;
 }
 
+/// A source for reading/loading events going backward from the end of an event stream.
+pub trait ReverseEventSource<A, E>: EventSource<A, E>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+{
+    /// Reads events from the event source for a given identifier, in descending order of event number.
+    ///
+    /// Only loads events before the event number provided in `before` (See [Before]), and will only load a maximum of
+    /// `max_count` events, if given. If not given, will read all remaining events.
+    fn read_events_reverse<I>(
+        &self,
+        id: &I,
+        before: Before,
+        max_count: Option<u64>,
+    ) -> Result<Option<Self::Events>, Self::Error>
+    where
+        I: AggregateId<A>;
+}
+
+/// A source for reading/loading events along with their associated metadata.
+pub trait EventWithMetadataSource<A, E, M>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+{
+    /// Represents the sequence of events with metadata read from the event source.
+    type Events: IntoIterator<Item = VersionedEventWithMetadata<E, M>>;
+
+    /// The error type.
+    type Error: CqrsError;
+
+    /// Reads events and associated metadata from the event source for a given identifier.
+    ///
+    /// Only loads events after the event number provided in `since` (See [Since]), and will only load a maximum of
+    /// `max_count` events, if given. If not given, will read all remaining events.
+    fn read_events_with_metadata<I>(
+        &self,
+        id: &I,
+        since: Since,
+        max_count: Option<u64>,
+    ) -> Result<Option<Self::Events>, Self::Error>
+    where
+        I: AggregateId<A>;
+}
+
+/// A source for reading/loading events across all aggregates.
+///
+/// Events are ordered by their global position in the store, which is given by [RawEvent::event_id].
+pub trait GlobalEventSource {
+    /// Represents the sequence of events read from the event source.
+    type Events: IntoIterator<Item = RawEvent>;
+
+    /// The error type.
+    type Error: CqrsError;
+
+    /// Reads events from the event source for all aggregates, in order of their global position.
+    ///
+    /// Only loads events after the event id provided in `since` (See [Since]), and will only load a maximum of
+    /// `max_count` events, if given. If not given, will read all remaining events.
+    fn read_all_events(
+        &self,
+        since: Since,
+        max_count: Option<u64>,
+    ) -> Result<Self::Events, Self::Error>;
+}
+
+impl<S> GlobalEventSource for &S
+where
+    S: GlobalEventSource + ?Sized,
+{
+    type Error = S::Error;
+    type Events = S::Events;
+
+    fn read_all_events(
+        &self,
+        since: Since,
+        max_count: Option<u64>,
+    ) -> Result<Self::Events, Self::Error> {
+        (**self).read_all_events(since, max_count)
+    }
+}
+
+impl<S> GlobalEventSource for Arc<S>
+where
+    S: GlobalEventSource + ?Sized,
+{
+    type Error = S::Error;
+    type Events = S::Events;
+
+    fn read_all_events(
+        &self,
+        since: Since,
+        max_count: Option<u64>,
+    ) -> Result<Self::Events, Self::Error> {
+        (**self).read_all_events(since, max_count)
+    }
+}
+
 /// A sink for writing/persisting events with associated metadata.
 pub trait EventSink<A, E, M>
 where
@@ -9,7 +9,7 @@ impl EventNumber {
     #[allow(unsafe_code)]
     pub const MIN_VALUE: EventNumber =
         // One is absolutely non-zero, and this is required for this to be usable in a `const` context.
-        EventNumber(unsafe {NonZeroU64::new_unchecked(1)});
+        EventNumber(unsafe { NonZeroU64::new_unchecked(1) });
 
     /// Attempts to create a new event number from a given number. Will return non if the given number is `0`.
     #[inline]
@@ -314,6 +314,8 @@ pub struct RawEvent {
     pub sequence: EventNumber,
     /// The event type.
     pub event_type: String,
+    /// The name of the format of the raw event payload, such as `"json"`.
+    pub payload_format: String,
     /// The raw event payload.
     pub payload: Vec<u8>,
 }
@@ -331,6 +333,8 @@ pub struct BorrowedRawEvent<'row> {
     pub sequence: EventNumber,
     /// The event type.
     pub event_type: &'row str,
+    /// The name of the format of the raw event payload, such as `"json"`.
+    pub payload_format: &'row str,
     /// The raw event payload.
     pub payload: &'row [u8],
 }
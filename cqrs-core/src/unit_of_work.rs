@@ -0,0 +1,142 @@
+use crate::{
+    aggregate::{Aggregate, AggregateEvent, AggregateId},
+    types::{CqrsError, EventNumber, Precondition},
+};
+use std::{fmt, marker::PhantomData};
+
+/// An append of events to the event stream of a single entity, staged as part of a [UnitOfWork].
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct StagedAppend<E, M> {
+    /// The identifier of the entity to which the events will be appended.
+    pub entity_id: String,
+
+    /// The events to append.
+    pub events: Vec<E>,
+
+    /// The precondition that must hold for the entity when the unit of work is committed.
+    pub precondition: Option<Precondition>,
+
+    /// The metadata associated with the events.
+    pub metadata: M,
+}
+
+/// A set of appends to the event streams of one or more entities, which are committed together or not at all.
+///
+/// Appends are applied in the order they were staged, so a later append to the same entity observes the events of
+/// an earlier one when its precondition is verified.
+pub struct UnitOfWork<A, E, M>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+{
+    appends: Vec<StagedAppend<E, M>>,
+    _phantom: PhantomData<fn(A)>,
+}
+
+impl<A, E, M> UnitOfWork<A, E, M>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+{
+    /// Constructs a new, empty unit of work.
+    pub fn new() -> Self {
+        UnitOfWork {
+            appends: Vec::new(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Stages an append of events to the given entity, with an optional precondition, and associated metadata.
+    pub fn append<I>(
+        &mut self,
+        id: &I,
+        events: Vec<E>,
+        precondition: Option<Precondition>,
+        metadata: M,
+    ) -> &mut Self
+    where
+        I: AggregateId<A>,
+    {
+        self.appends.push(StagedAppend {
+            entity_id: id.as_str().into(),
+            events,
+            precondition,
+            metadata,
+        });
+        self
+    }
+
+    /// The appends staged so far, in order.
+    pub fn appends(&self) -> &[StagedAppend<E, M>] {
+        &self.appends
+    }
+
+    /// Returns `true` if no appends have been staged.
+    pub fn is_empty(&self) -> bool {
+        self.appends.is_empty()
+    }
+
+    /// Consumes the unit of work, returning the staged appends, in order.
+    pub fn into_appends(self) -> Vec<StagedAppend<E, M>> {
+        self.appends
+    }
+}
+
+impl<A, E, M> Default for UnitOfWork<A, E, M>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A, E, M> fmt::Debug for UnitOfWork<A, E, M>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + fmt::Debug,
+    M: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UnitOfWork")
+            .field("aggregate_type", &A::aggregate_type())
+            .field("appends", &self.appends)
+            .finish()
+    }
+}
+
+/// A sink that can atomically commit a [UnitOfWork].
+pub trait UnitOfWorkSink<A, E, M>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+{
+    /// The error type.
+    type Error: CqrsError;
+
+    /// Commits all of the appends staged in the unit of work, or none of them if any fails.
+    ///
+    /// Returns the event number of the first event of each append, in the order the appends were staged.
+    fn commit(&self, unit_of_work: UnitOfWork<A, E, M>) -> Result<Vec<EventNumber>, Self::Error>;
+}
+
+/// An error indicating that the precondition of a staged append failed for a given entity.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct EntityPreconditionFailed {
+    /// The identifier of the entity whose precondition failed.
+    pub entity_id: String,
+
+    /// The precondition that failed.
+    pub precondition: Precondition,
+}
+
+impl fmt::Display for EntityPreconditionFailed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "precondition failed for entity {}: {}",
+            self.entity_id, self.precondition
+        )
+    }
+}
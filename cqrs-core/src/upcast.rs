@@ -0,0 +1,193 @@
+use crate::types::CqrsError;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+type UpcastFn = Box<dyn Fn(&[u8]) -> Result<Vec<u8>, String> + Send + Sync>;
+
+/// A chain of upcasters that transform serialized event payloads persisted with older schema versions into the
+/// latest schema version, before they are deserialized.
+///
+/// The schema version of each event type starts at zero, and each upcaster transforms a payload of one event type from
+/// a given schema version to the next. The latest schema version of an event type is the version produced by its last
+/// upcaster, which should match the [SerializableEvent::schema_version](crate::SerializableEvent::schema_version) of
+/// newly persisted events of that type.
+#[derive(Default)]
+pub struct EventUpcasters {
+    upcasters: HashMap<String, BTreeMap<u32, UpcastFn>>,
+}
+
+impl fmt::Debug for EventUpcasters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut event_types: Vec<_> = self
+            .upcasters
+            .iter()
+            .map(|(event_type, chain)| (event_type, chain.keys().collect::<Vec<_>>()))
+            .collect();
+        event_types.sort();
+
+        f.debug_struct("EventUpcasters")
+            .field("upcasters", &event_types)
+            .finish()
+    }
+}
+
+impl EventUpcasters {
+    /// Constructs an empty chain of upcasters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an upcaster transforming payloads of the given event type from `from_version` to the next schema version.
+    ///
+    /// An upcaster added for the same event type and version replaces the previous one.
+    pub fn with_upcaster<F, Err>(
+        mut self,
+        event_type: impl Into<String>,
+        from_version: u32,
+        upcaster: F,
+    ) -> Self
+    where
+        F: Fn(&[u8]) -> Result<Vec<u8>, Err> + Send + Sync + 'static,
+        Err: CqrsError,
+    {
+        self.upcasters.entry(event_type.into()).or_default().insert(
+            from_version,
+            Box::new(move |payload| upcaster(payload).map_err(|err| err.to_string())),
+        );
+        self
+    }
+
+    /// Gets the latest schema version of the given event type.
+    pub fn latest_version(&self, event_type: &str) -> u32 {
+        self.upcasters
+            .get(event_type)
+            .and_then(|chain| chain.keys().next_back())
+            .map_or(0, |&from_version| from_version + 1)
+    }
+
+    /// Transforms a payload of the given event type, persisted with the given schema version, into the latest schema
+    /// version.
+    ///
+    /// Returns an error if an upcaster fails, if there is no upcaster for one of the intervening versions, or if the
+    /// payload was persisted with a schema version newer than the latest.
+    pub fn upcast<'a>(
+        &self,
+        event_type: &str,
+        schema_version: u32,
+        payload: &'a [u8],
+    ) -> Result<Cow<'a, [u8]>, UpcastError> {
+        let latest_version = self.latest_version(event_type);
+        let error = |schema_version, reason| UpcastError {
+            event_type: event_type.to_owned(),
+            schema_version,
+            reason,
+        };
+
+        if schema_version > latest_version {
+            return Err(error(
+                schema_version,
+                format!("latest known schema version is {}", latest_version),
+            ));
+        }
+
+        let mut payload = Cow::Borrowed(payload);
+        for version in schema_version..latest_version {
+            let upcaster = self
+                .upcasters
+                .get(event_type)
+                .and_then(|chain| chain.get(&version))
+                .ok_or_else(|| error(version, String::from("no upcaster for schema version")))?;
+
+            payload = Cow::Owned(upcaster(&payload).map_err(|reason| error(version, reason))?);
+        }
+
+        Ok(payload)
+    }
+}
+
+/// An error while transforming an event payload with [EventUpcasters].
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct UpcastError {
+    /// The type of the event.
+    pub event_type: String,
+
+    /// The schema version that could not be upcast.
+    pub schema_version: u32,
+
+    /// A description of the error.
+    pub reason: String,
+}
+
+impl fmt::Display for UpcastError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unable to upcast event type {} from schema version {}: {}",
+            self.event_type, self.schema_version, self.reason
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upcasters() -> EventUpcasters {
+        EventUpcasters::new()
+            .with_upcaster("created", 0, |payload: &[u8]| {
+                Ok::<_, String>([payload, b"+1"].concat())
+            })
+            .with_upcaster("created", 1, |payload: &[u8]| {
+                if payload.is_empty() {
+                    Err(String::from("empty payload"))
+                } else {
+                    Ok([payload, b"+2"].concat())
+                }
+            })
+    }
+
+    #[test]
+    fn upcasts_payloads_through_each_later_version() {
+        let upcasters = upcasters();
+
+        assert_eq!(2, upcasters.latest_version("created"));
+        assert_eq!(0, upcasters.latest_version("deleted"));
+        assert_eq!(b"a+1+2", &*upcasters.upcast("created", 0, b"a").unwrap());
+        assert_eq!(b"a+2", &*upcasters.upcast("created", 1, b"a").unwrap());
+        assert_eq!(b"a", &*upcasters.upcast("created", 2, b"a").unwrap());
+        assert_eq!(b"a", &*upcasters.upcast("deleted", 0, b"a").unwrap());
+    }
+
+    #[test]
+    fn fails_to_upcast_unknown_or_invalid_payloads() {
+        let upcasters = upcasters().with_upcaster("renamed", 1, |payload: &[u8]| {
+            Ok::<_, String>(payload.to_owned())
+        });
+
+        assert_eq!(
+            UpcastError {
+                event_type: String::from("created"),
+                schema_version: 1,
+                reason: String::from("empty payload"),
+            },
+            upcasters.upcast("created", 1, b"").unwrap_err()
+        );
+        assert_eq!(
+            3,
+            upcasters
+                .upcast("created", 3, b"a")
+                .unwrap_err()
+                .schema_version
+        );
+        assert_eq!(
+            0,
+            upcasters
+                .upcast("renamed", 0, b"a")
+                .unwrap_err()
+                .schema_version
+        );
+    }
+}
@@ -1,86 +1,252 @@
-use crate::util::Sequence;
-use cqrs_core::{reactor::Reaction, CqrsError, EventNumber, RawEvent, Since};
+use crate::{
+    raw::upcast,
+    reactor::{DeadLetter, ReactionPosition, TransactionalReaction},
+    util::{as_seconds, Sequence},
+};
+use cqrs_core::{CqrsError, EventNumber, EventUpcasters, RawEvent, Since, UpcastError};
+use fallible_iterator::FallibleIterator;
 use num_traits::ToPrimitive;
-use postgres::{rows::Row, types::ToSql};
+use postgres::{rows::Row, types::ToSql, GenericConnection};
 use r2d2::{Pool, PooledConnection};
 use r2d2_postgres::PostgresConnectionManager;
-use std::{error, fmt, sync::Arc};
+use std::{error, fmt, marker::PhantomData, sync::Arc, time::Duration};
 
+/// An error while running a reaction.
 #[derive(Debug)]
-pub enum ReactorError<R, P = r2d2::Error, D = postgres::Error>
-where
-    R: Reaction,
-{
+pub enum ReactorError<E, P: ?Sized = r2d2::Error, D: ?Sized = postgres::Error> {
+    /// An error while getting a connection from the pool.
     Pool(Arc<P>),
+
+    /// An error from the PostgreSQL backend.
     Postgres(Arc<D>),
-    React(R::Error),
+
+    /// An error returned by the reaction.
+    React(E),
+
+    /// An event payload could not be upcast to the latest schema version of its event type.
+    Upcast(UpcastError),
 }
 
-impl<R, P, D> Clone for ReactorError<R, P, D>
+impl<E, P, D> Clone for ReactorError<E, P, D>
 where
-    R: Reaction,
-    R::Error: Clone,
+    E: Clone,
+    P: ?Sized,
+    D: ?Sized,
 {
     fn clone(&self) -> Self {
         match self {
             ReactorError::Pool(e) => ReactorError::Pool(e.clone()),
             ReactorError::Postgres(e) => ReactorError::Postgres(e.clone()),
             ReactorError::React(e) => ReactorError::React(e.clone()),
+            ReactorError::Upcast(e) => ReactorError::Upcast(e.clone()),
         }
     }
 }
 
-impl<R, P, D> ReactorError<R, P, D>
-where
-    R: Reaction,
-{
+impl<E, P, D> ReactorError<E, P, D> {
+    /// Creates a new pool error.
     pub fn pool(err: P) -> Self {
         ReactorError::Pool(Arc::new(err))
     }
 
+    /// Creates a new PostgreSQL error.
     pub fn postgres(err: D) -> Self {
         ReactorError::Postgres(Arc::new(err))
     }
 
-    pub fn react(err: R::Error) -> Self {
+    /// Creates a new reaction error.
+    pub fn react(err: E) -> Self {
         ReactorError::React(err)
     }
+
+    /// Creates a new upcast error.
+    pub fn upcast(err: UpcastError) -> Self {
+        ReactorError::Upcast(err)
+    }
 }
 
-impl<R, P, D> fmt::Display for ReactorError<R, P, D>
+impl<E, P, D> fmt::Display for ReactorError<E, P, D>
 where
-    R: Reaction,
-    R::Error: fmt::Display,
-    P: fmt::Display,
-    D: fmt::Display,
+    E: fmt::Display,
+    P: fmt::Display + ?Sized,
+    D: fmt::Display + ?Sized,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             ReactorError::Pool(ref err) => write!(f, "Pool error during reaction: {}", err),
             ReactorError::Postgres(ref err) => write!(f, "Postgres error during reaction: {}", err),
             ReactorError::React(ref err) => write!(f, "React error during reaction: {}", err),
+            ReactorError::Upcast(ref err) => write!(f, "Upcast error during reaction: {}", err),
         }
     }
 }
 
-pub trait DbPool<'conn> {
+pub(crate) mod sealed {
+    /// Prevents [DbPool](super::DbPool) and [DbConnection](super::DbConnection) from being implemented outside this
+    /// crate, so that methods can be added to them without breaking downstream code.
+    pub trait Sealed {}
+}
+
+/// A pool of connections used by a reactor.
+///
+/// This trait is sealed, and is only implemented for an `r2d2` pool of PostgreSQL connections.
+pub trait DbPool<'conn>: sealed::Sealed {
+    /// The connection type.
     type Connection: DbConnection<'conn> + 'conn;
+
+    /// The error type.
     type Error: CqrsError;
+
+    /// Gets a connection from the pool.
     fn get(&self) -> Result<Self::Connection, Self::Error>;
 }
 
-pub trait DbConnection<'conn> {
+/// A connection used by a reactor.
+///
+/// This trait is sealed, and is only implemented for a pooled PostgreSQL connection.
+pub trait DbConnection<'conn>: sealed::Sealed {
+    /// The error type.
     type Error: CqrsError;
+
+    /// Loads the position of the last event processed by a reaction.
     fn load_since(&self, reaction_name: &str) -> Result<Since, Self::Error>;
-    fn save_since(&self, reaction_name: &str, event_id: EventNumber) -> Result<(), Self::Error>;
+
+    /// Saves the position of the last event processed by a reaction. If `lease_owner` is given, the position is only
+    /// saved while that owner holds an unexpired lease on the reaction. Returns whether the position was saved.
+    fn save_since(
+        &self,
+        reaction_name: &str,
+        event_id: EventNumber,
+        lease_owner: Option<&str>,
+    ) -> Result<bool, Self::Error>;
+
+    /// Removes the position of a reaction, so that it next reacts from the beginning of the event stream.
+    fn delete_since(&self, reaction_name: &str) -> Result<(), Self::Error>;
+
+    /// Reads the position of every reaction, and its distance from the latest event id, in order of reaction name.
+    fn read_reaction_positions(&self) -> Result<Vec<ReactionPosition>, Self::Error>;
+
+    /// Reacts to an event within a new transaction, saving the position of the reaction under `checkpoint`, if given,
+    /// in the same transaction, as with [DbConnection::save_since]. The transaction is committed only if the reaction
+    /// succeeds and its position is saved. Returns whether the transaction was committed.
+    fn react_in_transaction<R>(
+        &self,
+        reaction: &mut R,
+        event: RawEvent,
+        checkpoint: Option<&str>,
+        lease_owner: Option<&str>,
+    ) -> Result<Result<bool, R::Error>, Self::Error>
+    where
+        R: TransactionalReaction;
+
+    /// Records an event that a reaction failed to react to, replacing any existing dead letter for the event.
+    fn save_dead_letter(
+        &self,
+        reaction_name: &str,
+        event_id: EventNumber,
+        error: &str,
+    ) -> Result<(), Self::Error>;
+
+    /// Removes the dead letter for an event.
+    fn delete_dead_letter(
+        &self,
+        reaction_name: &str,
+        event_id: EventNumber,
+    ) -> Result<(), Self::Error>;
+
+    /// Reads the dead letters for a reaction, in order of event id, optionally only those that have been requeued,
+    /// upcasting their payloads with `upcasters`.
+    fn read_dead_letters(
+        &self,
+        reaction_name: &str,
+        requeued_only: bool,
+        upcasters: &EventUpcasters,
+    ) -> Result<Result<Vec<DeadLetter>, UpcastError>, Self::Error>;
+
+    /// Marks the dead letters for the given events as requeued, returning the number of dead letters updated.
+    fn requeue_dead_letters(
+        &self,
+        reaction_name: &str,
+        event_ids: &[EventNumber],
+    ) -> Result<u64, Self::Error>;
+
+    /// Reads events after `since` using the given query and additional parameters, upcasting their payloads with
+    /// `upcasters`.
     fn read_all_events(
         &self,
         query: &str,
         since: Since,
         params: &[Box<dyn ToSql>],
-    ) -> Result<Vec<RawEvent>, Self::Error>;
+        upcasters: &EventUpcasters,
+    ) -> Result<Result<Vec<RawEvent>, UpcastError>, Self::Error>;
+
+    /// Starts listening for notifications on the given channel.
+    fn listen(&self, channel: &str) -> Result<(), Self::Error>;
+
+    /// Stops listening for notifications on the given channel.
+    fn unlisten(&self, channel: &str) -> Result<(), Self::Error>;
+
+    /// Waits up to `timeout` for a notification on any channel being listened to. Returns whether a notification was
+    /// received; any further pending notifications are consumed along with it.
+    fn wait_for_notification(&self, timeout: Duration) -> Result<bool, Self::Error>;
+
+    /// Acquires or renews the lease on a reaction for `duration`, returning whether `owner` holds the lease. The lease
+    /// can only be acquired if it is not held by another owner, or if that owner's lease has expired.
+    fn acquire_lease(
+        &self,
+        reaction_name: &str,
+        owner: &str,
+        duration: Duration,
+    ) -> Result<bool, Self::Error>;
+
+    /// Releases the lease on a reaction, if held by `owner`.
+    fn release_lease(&self, reaction_name: &str, owner: &str) -> Result<(), Self::Error>;
+}
+
+/// A connection listening for notifications on a channel, which stops listening when dropped so that it can be safely
+/// returned to its pool.
+pub(crate) struct Listener<'conn, C>
+where
+    C: DbConnection<'conn>,
+{
+    conn: C,
+    channel: &'static str,
+    _phantom: PhantomData<&'conn ()>,
+}
+
+impl<'conn, C> Listener<'conn, C>
+where
+    C: DbConnection<'conn>,
+{
+    pub(crate) fn listen(conn: C, channel: &'static str) -> Result<Self, C::Error> {
+        conn.listen(channel)?;
+        log::debug!("listening for notifications on {}", channel);
+
+        Ok(Listener {
+            conn,
+            channel,
+            _phantom: PhantomData,
+        })
+    }
+
+    pub(crate) fn wait(&self, timeout: Duration) -> Result<bool, C::Error> {
+        self.conn.wait_for_notification(timeout)
+    }
+}
+
+impl<'conn, C> Drop for Listener<'conn, C>
+where
+    C: DbConnection<'conn>,
+{
+    fn drop(&mut self) {
+        if let Err(err) = self.conn.unlisten(self.channel) {
+            log::warn!("failed to stop listening on {}: {}", self.channel, err);
+        }
+    }
 }
 
+impl sealed::Sealed for Pool<PostgresConnectionManager> {}
+
 impl<'conn> DbPool<'conn> for Pool<PostgresConnectionManager> {
     type Connection = PooledConnection<PostgresConnectionManager>;
     type Error = r2d2::Error;
@@ -90,6 +256,8 @@ impl<'conn> DbPool<'conn> for Pool<PostgresConnectionManager> {
     }
 }
 
+impl sealed::Sealed for PooledConnection<PostgresConnectionManager> {}
+
 impl<'conn> DbConnection<'conn> for PooledConnection<PostgresConnectionManager> {
     type Error = postgres::Error;
 
@@ -111,54 +279,171 @@ impl<'conn> DbConnection<'conn> for PooledConnection<PostgresConnectionManager>
         Ok(Since::BeginningOfStream)
     }
 
-    fn save_since(&self, reaction_name: &str, event_id: EventNumber) -> Result<(), Self::Error> {
+    fn save_since(
+        &self,
+        reaction_name: &str,
+        event_id: EventNumber,
+        lease_owner: Option<&str>,
+    ) -> Result<bool, Self::Error> {
+        save_since(&**self, reaction_name, event_id, lease_owner)
+    }
+
+    fn delete_since(&self, reaction_name: &str) -> Result<(), Self::Error> {
         let stmt = self.prepare_cached(
-            "INSERT INTO reactions (reaction_name, event_id) \
-             VALUES ($1, $2) \
-             ON CONFLICT (reaction_name) \
-             DO UPDATE SET event_id = EXCLUDED.event_id",
+            "DELETE FROM reactions \
+             WHERE reaction_name = $1",
+        )?;
+
+        stmt.execute(&[&reaction_name])?;
+
+        Ok(())
+    }
+
+    fn read_reaction_positions(&self) -> Result<Vec<ReactionPosition>, Self::Error> {
+        let stmt = self.prepare_cached(
+            "SELECT r.reaction_name, r.event_id, \
+             (SELECT COALESCE(MAX(e.event_id), 0) FROM events e) \
+             FROM reactions r \
+             ORDER BY r.reaction_name ASC",
+        )?;
+
+        let rows = stmt.query(&[])?;
+        let positions = (&rows)
+            .into_iter()
+            .map(|row| {
+                let event_id: Sequence = row.get(1);
+                let max_event_id: i64 = row.get(2);
+                ReactionPosition {
+                    reaction_name: row.get(0),
+                    event_id: event_id.0,
+                    event_id_distance: (max_event_id - event_id.0.get() as i64).max(0) as u64,
+                }
+            })
+            .collect();
+
+        Ok(positions)
+    }
+
+    fn react_in_transaction<R>(
+        &self,
+        reaction: &mut R,
+        event: RawEvent,
+        checkpoint: Option<&str>,
+        lease_owner: Option<&str>,
+    ) -> Result<Result<bool, R::Error>, Self::Error>
+    where
+        R: TransactionalReaction,
+    {
+        let trans = self.transaction()?;
+        let event_id = event.event_id;
+
+        if let Err(err) = reaction.react(event, &trans) {
+            return Ok(Err(err));
+        }
+
+        if let Some(reaction_name) = checkpoint {
+            if !save_since(&trans, reaction_name, event_id, lease_owner)? {
+                return Ok(Ok(false));
+            }
+        }
+        trans.commit()?;
+
+        Ok(Ok(true))
+    }
+
+    fn save_dead_letter(
+        &self,
+        reaction_name: &str,
+        event_id: EventNumber,
+        error: &str,
+    ) -> Result<(), Self::Error> {
+        let stmt = self.prepare_cached(
+            "INSERT INTO reaction_dead_letters (reaction_name, event_id, error) \
+             VALUES ($1, $2, $3) \
+             ON CONFLICT (reaction_name, event_id) \
+             DO UPDATE SET error = EXCLUDED.error, requeued = FALSE, timestamp = CURRENT_TIMESTAMP",
+        )?;
+
+        stmt.execute(&[&reaction_name, &(event_id.get() as i64), &error])?;
+
+        Ok(())
+    }
+
+    fn delete_dead_letter(
+        &self,
+        reaction_name: &str,
+        event_id: EventNumber,
+    ) -> Result<(), Self::Error> {
+        let stmt = self.prepare_cached(
+            "DELETE FROM reaction_dead_letters \
+             WHERE reaction_name = $1 AND event_id = $2",
         )?;
 
-        stmt.execute(&[
-            &reaction_name,
-            &event_id
-                .get()
-                .to_i64()
-                .expect("Not expecting event_id > several billions"),
-        ])?;
+        stmt.execute(&[&reaction_name, &(event_id.get() as i64)])?;
 
         Ok(())
     }
 
+    fn read_dead_letters(
+        &self,
+        reaction_name: &str,
+        requeued_only: bool,
+        upcasters: &EventUpcasters,
+    ) -> Result<Result<Vec<DeadLetter>, UpcastError>, Self::Error> {
+        let stmt = self.prepare_cached(
+            "SELECT e.event_id, e.aggregate_type, e.entity_id, e.sequence, e.event_type, \
+             COALESCE(e.binary_payload, convert_to(e.payload::text, 'UTF8')), e.schema_version, \
+             e.payload_format, d.error, d.requeued \
+             FROM reaction_dead_letters d \
+             INNER JOIN events e ON e.event_id = d.event_id \
+             WHERE d.reaction_name = $1 AND (d.requeued OR NOT $2) \
+             ORDER BY d.event_id ASC",
+        )?;
+
+        let rows = stmt.query(&[&reaction_name, &requeued_only])?;
+        let dead_letters = (&rows)
+            .into_iter()
+            .map(|row| {
+                Ok(DeadLetter {
+                    reaction_name: reaction_name.to_owned(),
+                    error: row.get(8),
+                    requeued: row.get(9),
+                    event: raw_event_from_row(row, upcasters)?,
+                })
+            })
+            .collect();
+
+        Ok(dead_letters)
+    }
+
+    fn requeue_dead_letters(
+        &self,
+        reaction_name: &str,
+        event_ids: &[EventNumber],
+    ) -> Result<u64, Self::Error> {
+        let event_ids: Vec<i64> = event_ids.iter().map(|id| id.get() as i64).collect();
+        let stmt = self.prepare_cached(
+            "UPDATE reaction_dead_letters \
+             SET requeued = TRUE \
+             WHERE reaction_name = $1 AND event_id = ANY ($2)",
+        )?;
+
+        stmt.execute(&[&reaction_name, &event_ids])
+    }
+
     fn read_all_events(
         &self,
         query: &str,
         since: Since,
         params: &[Box<ToSql>],
-    ) -> Result<Vec<RawEvent>, Self::Error> {
+        upcasters: &EventUpcasters,
+    ) -> Result<Result<Vec<RawEvent>, UpcastError>, Self::Error> {
         let last_sequence = match since {
             Since::BeginningOfStream => 0,
             Since::Event(x) => x.get(),
         } as i64;
 
-        fn handle_row(row: Row) -> RawEvent {
-            let event_id: Sequence = row.get(0);
-            let aggregate_type = row.get(1);
-            let entity_id = row.get(2);
-            let sequence: Sequence = row.get(3);
-            let event_type = row.get(4);
-            let payload = row.get_bytes(5).unwrap();
-            RawEvent {
-                event_id: event_id.0,
-                aggregate_type,
-                entity_id,
-                sequence: sequence.0,
-                event_type,
-                payload: payload.to_owned(),
-            }
-        };
-
-        let events: Vec<RawEvent>;
+        let events: Result<Vec<RawEvent>, UpcastError>;
         {
             let rows = {
                 let local_params: Vec<_> = ::std::iter::once::<&dyn ToSql>(&last_sequence)
@@ -168,9 +453,115 @@ impl<'conn> DbConnection<'conn> for PooledConnection<PostgresConnectionManager>
                 stmt.query(&local_params)?
             };
 
-            events = (&rows).into_iter().map(handle_row).collect();
+            events = (&rows)
+                .into_iter()
+                .map(|row| raw_event_from_row(row, upcasters))
+                .collect();
         }
 
         Ok(events)
     }
+
+    fn listen(&self, channel: &str) -> Result<(), Self::Error> {
+        self.batch_execute(&format!("LISTEN {}", channel))
+    }
+
+    fn unlisten(&self, channel: &str) -> Result<(), Self::Error> {
+        self.batch_execute(&format!("UNLISTEN {}", channel))
+    }
+
+    fn wait_for_notification(&self, timeout: Duration) -> Result<bool, Self::Error> {
+        let notifications = self.notifications();
+        let notified = notifications.timeout_iter(timeout).next()?.is_some();
+        if notified {
+            let mut pending = notifications.iter();
+            while pending.next()?.is_some() {}
+        }
+
+        Ok(notified)
+    }
+
+    fn acquire_lease(
+        &self,
+        reaction_name: &str,
+        owner: &str,
+        duration: Duration,
+    ) -> Result<bool, Self::Error> {
+        let stmt = self.prepare_cached(
+            "INSERT INTO reaction_leases (reaction_name, owner, expires_at) \
+             VALUES ($1, $2, CURRENT_TIMESTAMP + $3 * INTERVAL '1 second') \
+             ON CONFLICT (reaction_name) \
+             DO UPDATE SET owner = EXCLUDED.owner, expires_at = EXCLUDED.expires_at \
+             WHERE reaction_leases.owner = EXCLUDED.owner \
+             OR reaction_leases.expires_at < CURRENT_TIMESTAMP",
+        )?;
+
+        let modified_count = stmt.execute(&[&reaction_name, &owner, &as_seconds(duration)])?;
+
+        Ok(modified_count > 0)
+    }
+
+    fn release_lease(&self, reaction_name: &str, owner: &str) -> Result<(), Self::Error> {
+        let stmt = self.prepare_cached(
+            "DELETE FROM reaction_leases \
+             WHERE reaction_name = $1 AND owner = $2",
+        )?;
+
+        stmt.execute(&[&reaction_name, &owner])?;
+
+        Ok(())
+    }
+}
+
+fn raw_event_from_row(row: Row, upcasters: &EventUpcasters) -> Result<RawEvent, UpcastError> {
+    let event_id: Sequence = row.get(0);
+    let aggregate_type = row.get(1);
+    let entity_id = row.get(2);
+    let sequence: Sequence = row.get(3);
+    let event_type: String = row.get(4);
+    let payload = row.get_bytes(5).unwrap();
+    let schema_version = row.get(6);
+    let payload = upcast(upcasters, &event_type, schema_version, payload)?;
+    Ok(RawEvent {
+        event_id: event_id.0,
+        aggregate_type,
+        entity_id,
+        sequence: sequence.0,
+        event_type,
+        payload_format: row.get(7),
+        payload: payload.into_owned(),
+    })
+}
+
+fn save_since<C>(
+    conn: &C,
+    reaction_name: &str,
+    event_id: EventNumber,
+    lease_owner: Option<&str>,
+) -> Result<bool, postgres::Error>
+where
+    C: GenericConnection,
+{
+    // The lease row is locked so that it cannot be taken over before the position is committed.
+    let stmt = conn.prepare_cached(
+        "INSERT INTO reactions (reaction_name, event_id) \
+         SELECT $1::text, $2::bigint \
+         WHERE $3::text IS NULL OR EXISTS ( \
+         SELECT 1 FROM reaction_leases \
+         WHERE reaction_name = $1 AND owner = $3 AND expires_at > clock_timestamp() \
+         FOR SHARE) \
+         ON CONFLICT (reaction_name) \
+         DO UPDATE SET event_id = EXCLUDED.event_id",
+    )?;
+
+    let modified_count = stmt.execute(&[
+        &reaction_name,
+        &event_id
+            .get()
+            .to_i64()
+            .expect("Not expecting event_id > several billions"),
+        &lease_owner,
+    ])?;
+
+    Ok(modified_count > 0)
 }
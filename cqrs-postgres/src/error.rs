@@ -1,4 +1,4 @@
-use cqrs_core::CqrsError;
+use cqrs_core::{CqrsError, UpcastError};
 use std::fmt;
 
 /// An error while attempting to persist an event or snapshot.
@@ -12,6 +12,9 @@ pub enum PersistError<E: CqrsError> {
 
     /// The operation failed because there was a serialization error.
     SerializationError(E),
+
+    /// The event cannot be serialized in the store's payload format.
+    UnsupportedPayloadFormat(String),
 }
 
 impl<E: CqrsError> fmt::Display for PersistError<E> {
@@ -20,6 +23,9 @@ impl<E: CqrsError> fmt::Display for PersistError<E> {
             PersistError::Postgres(ref e) => write!(f, "postgres error: {}", e),
             PersistError::PreconditionFailed(ref e) => write!(f, "precondition error: {}", e),
             PersistError::SerializationError(ref e) => write!(f, "serialization error: {}", e),
+            PersistError::UnsupportedPayloadFormat(ref s) => {
+                write!(f, "unsupported payload format: {}", s)
+            }
         }
     }
 }
@@ -36,6 +42,41 @@ impl<E: CqrsError> From<cqrs_core::Precondition> for PersistError<E> {
     }
 }
 
+/// An error while attempting to commit a unit of work.
+#[derive(Debug)]
+pub enum UnitOfWorkError<E: CqrsError> {
+    /// An error from the PostgreSQL backend.
+    Postgres(postgres::Error),
+
+    /// The operation failed because the precondition for one of the staged appends failed.
+    PreconditionFailed(cqrs_core::EntityPreconditionFailed),
+
+    /// The operation failed because there was a serialization error.
+    SerializationError(E),
+
+    /// An event cannot be serialized in the store's payload format.
+    UnsupportedPayloadFormat(String),
+}
+
+impl<E: CqrsError> fmt::Display for UnitOfWorkError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UnitOfWorkError::Postgres(ref e) => write!(f, "postgres error: {}", e),
+            UnitOfWorkError::PreconditionFailed(ref e) => write!(f, "precondition error: {}", e),
+            UnitOfWorkError::SerializationError(ref e) => write!(f, "serialization error: {}", e),
+            UnitOfWorkError::UnsupportedPayloadFormat(ref s) => {
+                write!(f, "unsupported payload format: {}", s)
+            }
+        }
+    }
+}
+
+impl<E: CqrsError> From<postgres::Error> for UnitOfWorkError<E> {
+    fn from(err: postgres::Error) -> Self {
+        UnitOfWorkError::Postgres(err)
+    }
+}
+
 /// An error while attempting to load an event or snapshot.
 #[derive(Debug)]
 pub enum LoadError<E: CqrsError> {
@@ -47,6 +88,9 @@ pub enum LoadError<E: CqrsError> {
 
     /// The operation failed because there was a deserialization error.
     DeserializationError(E),
+
+    /// The event payload could not be upcast to the latest schema version of its event type.
+    UpcastError(UpcastError),
 }
 
 impl<E: CqrsError> fmt::Display for LoadError<E> {
@@ -55,6 +99,7 @@ impl<E: CqrsError> fmt::Display for LoadError<E> {
             LoadError::Postgres(ref e) => write!(f, "postgres error: {}", e),
             LoadError::DeserializationError(ref e) => write!(f, "deserialization error: {}", e),
             LoadError::UnknownEventType(ref s) => write!(f, "unknown event type: {}", s),
+            LoadError::UpcastError(ref e) => write!(f, "upcast error: {}", e),
         }
     }
 }
@@ -64,3 +109,63 @@ impl<E: CqrsError> From<postgres::Error> for LoadError<E> {
         LoadError::Postgres(err)
     }
 }
+
+/// An error while attempting to migrate the database schema.
+#[derive(Debug)]
+pub enum MigrationError {
+    /// An error from the PostgreSQL backend.
+    Postgres(postgres::Error),
+
+    /// A migration that has already been applied to the database differs from the migration known to this executable.
+    ChecksumMismatch {
+        /// The version of the modified migration.
+        version: i32,
+
+        /// The name of the modified migration.
+        name: &'static str,
+    },
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MigrationError::Postgres(ref e) => write!(f, "postgres error: {}", e),
+            MigrationError::ChecksumMismatch { version, name } => write!(
+                f,
+                "applied migration {} ({}) has been modified",
+                version, name
+            ),
+        }
+    }
+}
+
+impl From<postgres::Error> for MigrationError {
+    fn from(err: postgres::Error) -> Self {
+        MigrationError::Postgres(err)
+    }
+}
+
+/// An error from a store that checks out connections from a connection pool.
+#[derive(Debug)]
+pub enum PooledStoreError<E: CqrsError> {
+    /// An error while checking out a connection from the pool.
+    Pool(r2d2::Error),
+
+    /// An error from the underlying store.
+    Store(E),
+}
+
+impl<E: CqrsError> fmt::Display for PooledStoreError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PooledStoreError::Pool(ref e) => write!(f, "pool error: {}", e),
+            PooledStoreError::Store(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl<E: CqrsError> From<r2d2::Error> for PooledStoreError<E> {
+    fn from(err: r2d2::Error) -> Self {
+        PooledStoreError::Pool(err)
+    }
+}
@@ -0,0 +1,61 @@
+/// The format in which a store persists event payloads.
+///
+/// The format of each event is recorded in the `payload_format` column of the `events` table, and passed to
+/// `DeserializableEvent::deserialize_event_from_buffer_in_format` when the event is read, so a table may hold events in
+/// several formats. Snapshots are always persisted as JSON.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum PayloadFormat {
+    /// Payloads are JSON, stored in the `payload` column as `jsonb`.
+    Json,
+
+    /// Payloads are in the named binary format, such as `"msgpack"`, `"cbor"` or `"bincode"`, stored in the
+    /// `binary_payload` column as `bytea`.
+    ///
+    /// The name must not be `"json"`, as it could not be told apart from [PayloadFormat::Json]; stores panic when given
+    /// such a format.
+    Binary(&'static str),
+}
+
+impl PayloadFormat {
+    /// The name of the format, as recorded in the `payload_format` column.
+    pub fn name(self) -> &'static str {
+        match self {
+            PayloadFormat::Json => "json",
+            PayloadFormat::Binary(name) => name,
+        }
+    }
+
+    /// Panics if the format is a binary format named `"json"`.
+    pub(crate) fn assert_valid(self) {
+        if let PayloadFormat::Binary(name) = self {
+            assert_ne!(
+                name, "json",
+                "a binary payload format cannot be named \"json\""
+            );
+        }
+    }
+}
+
+impl Default for PayloadFormat {
+    #[inline]
+    fn default() -> Self {
+        PayloadFormat::Json
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PayloadFormat;
+
+    #[test]
+    fn accepts_json_and_named_binary_formats() {
+        PayloadFormat::Json.assert_valid();
+        PayloadFormat::Binary("msgpack").assert_valid();
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot be named")]
+    fn rejects_a_binary_format_named_json() {
+        PayloadFormat::Binary("json").assert_valid();
+    }
+}
@@ -26,14 +26,27 @@ extern crate static_assertions;
 
 mod db_wrapper;
 mod error;
-mod reactor;
+mod format;
+mod migrations;
+mod pooled;
+mod retention;
 mod store;
+mod supervisor;
 mod util;
 
 pub mod raw;
+pub mod reactor;
 
 #[doc(inline)]
-pub use crate::error::{LoadError, PersistError};
+pub use crate::error::{
+    LoadError, MigrationError, PersistError, PooledStoreError, UnitOfWorkError,
+};
+#[doc(inline)]
+pub use crate::format::PayloadFormat;
+#[doc(inline)]
+pub use crate::pooled::PooledPostgresStore;
+#[doc(inline)]
+pub use crate::retention::SnapshotRetention;
 #[doc(inline)]
 pub use crate::store::PostgresStore;
 
@@ -57,4 +70,50 @@ mod tests {
     fn postgres_store_is_an_entity_sink() {
         assert_impl!(PostgresStore<TodoAggregate, TodoEvent, TodoMetadata>, cqrs::EntitySink<TodoAggregate, TodoEvent, TodoMetadata>);
     }
+
+    #[test]
+    fn pooled_postgres_store_is_an_entity_store() {
+        assert_impl!(PooledPostgresStore<TodoAggregate, TodoEvent, TodoMetadata>, cqrs::EntityStore<TodoAggregate, TodoEvent, TodoMetadata>);
+    }
+
+    #[test]
+    fn pooled_postgres_store_is_shareable_between_threads() {
+        assert_impl!(PooledPostgresStore<TodoAggregate, TodoEvent, TodoMetadata>, Send, Sync);
+    }
+
+    #[test]
+    fn postgres_store_is_a_global_event_source() {
+        assert_impl!(PostgresStore<TodoAggregate, TodoEvent, TodoMetadata>, cqrs::GlobalEventSource);
+    }
+
+    #[test]
+    fn postgres_store_is_an_event_with_metadata_source_with_any_snapshot_strategy() {
+        struct SnapshotEvery(i64);
+
+        impl cqrs::SnapshotStrategy for SnapshotEvery {
+            fn snapshot_recommendation(
+                &self,
+                version: cqrs::Version,
+                last_snapshot_version: Option<cqrs::Version>,
+            ) -> cqrs::SnapshotRecommendation {
+                if version - last_snapshot_version.unwrap_or_default() >= self.0 {
+                    cqrs::SnapshotRecommendation::ShouldSnapshot
+                } else {
+                    cqrs::SnapshotRecommendation::DoNotSnapshot
+                }
+            }
+        }
+
+        assert_impl!(PostgresStore<TodoAggregate, TodoEvent, TodoMetadata, SnapshotEvery>, cqrs::EventWithMetadataSource<TodoAggregate, TodoEvent, TodoMetadata>);
+    }
+
+    #[test]
+    fn postgres_store_is_a_unit_of_work_sink() {
+        assert_impl!(PostgresStore<TodoAggregate, TodoEvent, TodoMetadata>, cqrs::UnitOfWorkSink<TodoAggregate, TodoEvent, TodoMetadata>);
+    }
+
+    #[test]
+    fn postgres_reactor_is_a_reactor() {
+        assert_impl!(reactor::PostgresReactor, cqrs::reactor::Reactor);
+    }
 }
@@ -0,0 +1,192 @@
+//! Ordered schema migrations for the PostgreSQL event store.
+
+use crate::error::MigrationError;
+use postgres::{Connection, GenericConnection};
+
+/// A single schema migration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+/// All known migrations, in the order in which they must be applied.
+///
+/// Migrations that have been released must never be modified; make changes by adding a new migration instead. The
+/// first migration was released before the runner recorded migrations, so it records its own version.
+pub(crate) const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_tables",
+        sql: include_str!("migrations/01_create_tables.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "add_snapshot_timestamp",
+        sql: include_str!("migrations/02_add_snapshot_timestamp.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "create_reactions",
+        sql: include_str!("migrations/03_create_reactions.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "create_events_notify_trigger",
+        sql: include_str!("migrations/04_create_events_notify_trigger.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "create_reaction_dead_letters",
+        sql: include_str!("migrations/05_create_reaction_dead_letters.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "create_reaction_leases",
+        sql: include_str!("migrations/06_create_reaction_leases.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "add_events_transaction_id",
+        sql: include_str!("migrations/07_add_events_transaction_id.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "add_snapshot_schema_version",
+        sql: include_str!("migrations/08_add_snapshot_schema_version.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "add_events_schema_version",
+        sql: include_str!("migrations/09_add_events_schema_version.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "add_events_binary_payload",
+        sql: include_str!("migrations/10_add_events_binary_payload.sql"),
+    },
+];
+
+/// The version of the database schema after all known migrations have been applied.
+pub(crate) fn latest_version() -> i32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/// Gets the version of the latest migration applied to the database.
+pub(crate) fn current_version<C>(conn: &C) -> Result<i32, postgres::Error>
+where
+    C: GenericConnection,
+{
+    let current_version = conn
+        .query("SELECT MAX(version) FROM migrations", &[])?
+        .iter()
+        .next()
+        .and_then(|r| r.get(0))
+        .unwrap_or_default();
+
+    Ok(current_version)
+}
+
+/// Applies all pending migrations within a single transaction.
+///
+/// Checksums of previously applied migrations are verified first, and nothing is applied if any of them have been
+/// modified. Migrations applied before checksums were recorded have their checksums filled in, as does a migration
+/// which records its own version.
+pub(crate) fn run_migrations(conn: &Connection) -> Result<(), MigrationError> {
+    conn.batch_execute(include_str!("migrations/00_create_migrations.sql"))?;
+
+    let trans = conn.transaction()?;
+    trans.batch_execute("LOCK TABLE migrations IN EXCLUSIVE MODE")?;
+
+    let current_version = current_version(&trans)?;
+
+    {
+        let verify_stmt = trans.prepare_cached(
+            "SELECT checksum IS NULL, checksum = md5($2) \
+             FROM migrations \
+             WHERE version = $1",
+        )?;
+        let backfill_stmt = trans.prepare_cached(
+            "UPDATE migrations \
+             SET name = $2, checksum = md5($3) \
+             WHERE version = $1",
+        )?;
+
+        for migration in MIGRATIONS.iter().filter(|m| m.version <= current_version) {
+            let rows = verify_stmt.query(&[&migration.version, &migration.sql])?;
+            if let Some(row) = rows.iter().next() {
+                let missing: bool = row.get(0);
+                if missing {
+                    backfill_stmt.execute(&[
+                        &migration.version,
+                        &migration.name,
+                        &migration.sql,
+                    ])?;
+                    log::debug!(
+                        "migration {} ({}): recorded checksum",
+                        migration.version,
+                        migration.name
+                    );
+                } else if !row.get::<_, bool>(1) {
+                    return Err(MigrationError::ChecksumMismatch {
+                        version: migration.version,
+                        name: migration.name,
+                    });
+                }
+            }
+        }
+    }
+
+    {
+        let record_stmt = trans.prepare_cached(
+            "INSERT INTO migrations (version, name, checksum) \
+             VALUES ($1, $2, md5($3)) \
+             ON CONFLICT (version) DO UPDATE \
+             SET name = EXCLUDED.name, checksum = EXCLUDED.checksum",
+        )?;
+
+        for migration in MIGRATIONS.iter().filter(|m| m.version > current_version) {
+            trans.batch_execute(migration.sql)?;
+            record_stmt.execute(&[&migration.version, &migration.name, &migration.sql])?;
+            log::info!(
+                "migration {} ({}): applied",
+                migration.version,
+                migration.name
+            );
+        }
+    }
+
+    trans.commit()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migration_versions_are_sequential_from_one() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, index as i32 + 1);
+        }
+        assert_eq!(latest_version(), MIGRATIONS.len() as i32);
+    }
+
+    #[test]
+    fn only_the_first_migration_records_itself() {
+        assert!(MIGRATIONS[0]
+            .sql
+            .contains("INSERT INTO migrations (version) VALUES (1);"));
+
+        for migration in &MIGRATIONS[1..] {
+            assert!(
+                !migration.sql.contains("INSERT INTO migrations"),
+                "migration {} ({}) must not insert into the migrations table",
+                migration.version,
+                migration.name
+            );
+        }
+    }
+}
@@ -2,3 +2,6 @@ CREATE TABLE IF NOT EXISTS migrations (
   version int NOT NULL PRIMARY KEY,
   timestamp timestamp with time zone DEFAULT (CURRENT_TIMESTAMP)
 );
+
+ALTER TABLE migrations ADD COLUMN IF NOT EXISTS name text;
+ALTER TABLE migrations ADD COLUMN IF NOT EXISTS checksum text;
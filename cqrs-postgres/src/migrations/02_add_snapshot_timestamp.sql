@@ -0,0 +1,2 @@
+ALTER TABLE snapshots
+  ADD COLUMN timestamp timestamp with time zone DEFAULT (CURRENT_TIMESTAMP);
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS reactions (
+  reaction_name text NOT NULL PRIMARY KEY,
+  event_id bigint CHECK (event_id > 0) NOT NULL,
+  timestamp timestamp with time zone DEFAULT (CURRENT_TIMESTAMP)
+);
@@ -0,0 +1,10 @@
+CREATE OR REPLACE FUNCTION notify_events_inserted() RETURNS trigger AS $$
+BEGIN
+  PERFORM pg_notify('events_inserted', NEW.aggregate_type);
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER events_inserted
+  AFTER INSERT ON events
+  FOR EACH ROW EXECUTE PROCEDURE notify_events_inserted();
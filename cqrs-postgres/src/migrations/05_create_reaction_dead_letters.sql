@@ -0,0 +1,8 @@
+CREATE TABLE reaction_dead_letters (
+  reaction_name text NOT NULL,
+  event_id bigint NOT NULL REFERENCES events (event_id),
+  error text NOT NULL,
+  requeued boolean NOT NULL DEFAULT FALSE,
+  timestamp timestamp with time zone DEFAULT (CURRENT_TIMESTAMP),
+  PRIMARY KEY (reaction_name, event_id)
+);
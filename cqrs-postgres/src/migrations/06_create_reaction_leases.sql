@@ -0,0 +1,5 @@
+CREATE TABLE reaction_leases (
+  reaction_name text NOT NULL PRIMARY KEY,
+  owner text NOT NULL,
+  expires_at timestamp with time zone NOT NULL
+);
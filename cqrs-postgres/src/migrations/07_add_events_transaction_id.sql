@@ -0,0 +1,6 @@
+-- Existing events are backfilled with transaction id 0 rather than the id of this transaction, so that they sort before
+-- every event inserted afterwards, including events inserted by transactions that began before this one.
+ALTER TABLE events ADD COLUMN transaction_id bigint NOT NULL DEFAULT 0;
+ALTER TABLE events ALTER COLUMN transaction_id SET DEFAULT txid_current();
+
+CREATE INDEX events_transaction_id_event_id_idx ON events (transaction_id, event_id);
@@ -0,0 +1,2 @@
+ALTER TABLE snapshots
+  ADD COLUMN schema_version bigint NOT NULL DEFAULT 0;
@@ -0,0 +1,5 @@
+ALTER TABLE events
+  ALTER COLUMN payload DROP NOT NULL,
+  ADD COLUMN payload_format text NOT NULL DEFAULT 'json',
+  ADD COLUMN binary_payload bytea,
+  ADD CONSTRAINT events_payload_check CHECK ((payload IS NULL) <> (binary_payload IS NULL));
@@ -0,0 +1,307 @@
+use crate::{
+    error::{LoadError, PersistError, PooledStoreError, UnitOfWorkError},
+    format::PayloadFormat,
+    raw::GlobalOrdering,
+    retention::SnapshotRetention,
+    store::PostgresStore,
+};
+use cqrs_core::{
+    Aggregate, AggregateEvent, AggregateId, Before, DeserializableEvent, EventNumber, EventSink,
+    EventSource, EventUpcasters, EventWithMetadataSource, GlobalEventSource, NeverSnapshot,
+    Precondition, RawEvent, ReverseEventSource, SerializableEvent, Since, SnapshotSink,
+    SnapshotSource, SnapshotStrategy, UnitOfWork, UnitOfWorkSink, Version, VersionedAggregate,
+    VersionedEvent, VersionedEventWithMetadata,
+};
+use r2d2::Pool;
+use r2d2_postgres::PostgresConnectionManager;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fmt, marker::PhantomData, sync::Arc};
+use void::Void;
+
+/// A PostgreSQL storage backend that owns a connection pool.
+///
+/// A connection is checked out of the pool for the duration of each operation, so the store can be shared freely
+/// between threads, e.g. through an `Arc`.
+#[derive(Clone)]
+pub struct PooledPostgresStore<A, E, M, S = NeverSnapshot>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+    S: SnapshotStrategy,
+{
+    pool: Pool<PostgresConnectionManager>,
+    snapshot_strategy: S,
+    snapshot_retention: SnapshotRetention,
+    global_ordering: GlobalOrdering,
+    upcasters: Arc<EventUpcasters>,
+    payload_format: PayloadFormat,
+    _phantom: PhantomData<fn(A, E, M)>,
+}
+
+impl<A, E, M, S> fmt::Debug for PooledPostgresStore<A, E, M, S>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+    S: SnapshotStrategy + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PooledPostgresStore")
+            .field("pool", &self.pool)
+            .field("strategy", &self.snapshot_strategy)
+            .field("retention", &self.snapshot_retention)
+            .field("global_ordering", &self.global_ordering)
+            .field("upcasters", &self.upcasters)
+            .field("payload_format", &self.payload_format)
+            .field("phantom", &self._phantom)
+            .finish()
+    }
+}
+
+impl<A, E, M, S> PooledPostgresStore<A, E, M, S>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+    S: SnapshotStrategy + Default,
+{
+    /// Constructs a store based on a provided connection pool using the default snapshot strategy.
+    pub fn new(pool: Pool<PostgresConnectionManager>) -> Self {
+        Self::with_snapshot_strategy(pool, S::default())
+    }
+}
+
+impl<A, E, M, S> PooledPostgresStore<A, E, M, S>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+    S: SnapshotStrategy,
+{
+    /// Constructs a store based on a provided connection pool and snapshot strategy.
+    pub fn with_snapshot_strategy(
+        pool: Pool<PostgresConnectionManager>,
+        snapshot_strategy: S,
+    ) -> Self {
+        PooledPostgresStore {
+            pool,
+            snapshot_strategy,
+            snapshot_retention: SnapshotRetention::default(),
+            global_ordering: GlobalOrdering::default(),
+            upcasters: Arc::default(),
+            payload_format: PayloadFormat::default(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Sets the policy for which snapshots are retained when a new snapshot is persisted.
+    pub fn with_snapshot_retention(mut self, snapshot_retention: SnapshotRetention) -> Self {
+        self.snapshot_retention = snapshot_retention;
+        self
+    }
+
+    /// Sets the order in which events are read from the global event stream.
+    pub fn with_global_ordering(mut self, global_ordering: GlobalOrdering) -> Self {
+        self.global_ordering = global_ordering;
+        self
+    }
+
+    /// Sets the upcasters applied to event payloads persisted with older schema versions before they are deserialized.
+    ///
+    /// Newly appended events are recorded with their [SerializableEvent::schema_version].
+    pub fn with_upcasters(mut self, upcasters: impl Into<Arc<EventUpcasters>>) -> Self {
+        self.upcasters = upcasters.into();
+        self
+    }
+
+    /// Sets the format in which appended event payloads are serialized and persisted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the format is a [PayloadFormat::Binary] format named `"json"`.
+    pub fn with_payload_format(mut self, payload_format: PayloadFormat) -> Self {
+        payload_format.assert_valid();
+        self.payload_format = payload_format;
+        self
+    }
+
+    /// Gets the underlying connection pool.
+    pub fn pool(&self) -> &Pool<PostgresConnectionManager> {
+        &self.pool
+    }
+
+    /// Checks out a connection from the pool and runs the provided function against a transient [PostgresStore]
+    /// using that connection.
+    pub fn with_store<T, Err, F>(&self, f: F) -> Result<T, PooledStoreError<Err>>
+    where
+        S: Clone,
+        Err: cqrs_core::CqrsError,
+        F: FnOnce(&PostgresStore<A, E, M, S>) -> Result<T, Err>,
+    {
+        let conn = self.pool.get()?;
+        let store = PostgresStore::with_snapshot_strategy(&conn, self.snapshot_strategy.clone())
+            .with_snapshot_retention(self.snapshot_retention)
+            .with_global_ordering(self.global_ordering)
+            .with_upcasters(Arc::clone(&self.upcasters))
+            .with_payload_format(self.payload_format);
+        f(&store).map_err(PooledStoreError::Store)
+    }
+}
+
+impl<A, E, M, S> EventSink<A, E, M> for PooledPostgresStore<A, E, M, S>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + SerializableEvent + fmt::Debug,
+    M: Serialize + fmt::Debug,
+    S: SnapshotStrategy + Clone,
+{
+    type Error = PooledStoreError<PersistError<<E as SerializableEvent>::Error>>;
+
+    fn append_events<I>(
+        &self,
+        id: &I,
+        events: &[E],
+        precondition: Option<Precondition>,
+        metadata: M,
+    ) -> Result<EventNumber, Self::Error>
+    where
+        I: AggregateId<A>,
+    {
+        self.with_store(|store| store.append_events(id, events, precondition, metadata))
+    }
+}
+
+impl<A, E, M, S> UnitOfWorkSink<A, E, M> for PooledPostgresStore<A, E, M, S>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + SerializableEvent + fmt::Debug,
+    M: Serialize + fmt::Debug,
+    S: SnapshotStrategy + Clone,
+{
+    type Error = PooledStoreError<UnitOfWorkError<<E as SerializableEvent>::Error>>;
+
+    fn commit(&self, unit_of_work: UnitOfWork<A, E, M>) -> Result<Vec<EventNumber>, Self::Error> {
+        self.with_store(|store| store.commit(unit_of_work))
+    }
+}
+
+impl<A, E, M, S> EventSource<A, E> for PooledPostgresStore<A, E, M, S>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + DeserializableEvent,
+    S: SnapshotStrategy + Clone,
+{
+    type Error = PooledStoreError<LoadError<<E as DeserializableEvent>::Error>>;
+    type Events = Vec<VersionedEvent<E>>;
+
+    fn read_events<I>(
+        &self,
+        id: &I,
+        since: Since,
+        max_count: Option<u64>,
+    ) -> Result<Option<Self::Events>, Self::Error>
+    where
+        I: AggregateId<A>,
+    {
+        self.with_store(|store| store.read_events(id, since, max_count))
+    }
+}
+
+impl<A, E, M, S> ReverseEventSource<A, E> for PooledPostgresStore<A, E, M, S>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + DeserializableEvent,
+    S: SnapshotStrategy + Clone,
+{
+    fn read_events_reverse<I>(
+        &self,
+        id: &I,
+        before: Before,
+        max_count: Option<u64>,
+    ) -> Result<Option<Self::Events>, Self::Error>
+    where
+        I: AggregateId<A>,
+    {
+        self.with_store(|store| store.read_events_reverse(id, before, max_count))
+    }
+}
+
+impl<A, E, M, S> EventWithMetadataSource<A, E, M> for PooledPostgresStore<A, E, M, S>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + DeserializableEvent,
+    M: DeserializeOwned,
+    S: SnapshotStrategy + Clone + Default,
+{
+    type Error = PooledStoreError<LoadError<<E as DeserializableEvent>::Error>>;
+    type Events = Vec<VersionedEventWithMetadata<E, M>>;
+
+    fn read_events_with_metadata<I>(
+        &self,
+        id: &I,
+        since: Since,
+        max_count: Option<u64>,
+    ) -> Result<Option<Self::Events>, Self::Error>
+    where
+        I: AggregateId<A>,
+    {
+        self.with_store(|store| {
+            EventWithMetadataSource::read_events_with_metadata(store, id, since, max_count)
+        })
+    }
+}
+
+impl<A, E, M, S> GlobalEventSource for PooledPostgresStore<A, E, M, S>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+    S: SnapshotStrategy + Clone,
+{
+    type Error = PooledStoreError<LoadError<Void>>;
+    type Events = Vec<RawEvent>;
+
+    fn read_all_events(
+        &self,
+        since: Since,
+        max_count: Option<u64>,
+    ) -> Result<Self::Events, Self::Error> {
+        self.with_store(|store| store.read_all_events(since, max_count))
+    }
+}
+
+impl<A, E, M, S> SnapshotSink<A> for PooledPostgresStore<A, E, M, S>
+where
+    A: Aggregate + Serialize + fmt::Debug,
+    E: AggregateEvent<A>,
+    S: SnapshotStrategy + Clone,
+{
+    type Error = PooledStoreError<PersistError<serde_json::Error>>;
+
+    fn persist_snapshot<I>(
+        &self,
+        id: &I,
+        aggregate: &A,
+        version: Version,
+        last_snapshot_version: Option<Version>,
+    ) -> Result<Version, Self::Error>
+    where
+        I: AggregateId<A>,
+    {
+        self.with_store(|store| {
+            store.persist_snapshot(id, aggregate, version, last_snapshot_version)
+        })
+    }
+}
+
+impl<A, E, M, S> SnapshotSource<A> for PooledPostgresStore<A, E, M, S>
+where
+    A: Aggregate + DeserializeOwned,
+    E: AggregateEvent<A>,
+    S: SnapshotStrategy + Clone,
+{
+    type Error = PooledStoreError<postgres::Error>;
+
+    fn get_snapshot<I>(&self, id: &I) -> Result<Option<VersionedAggregate<A>>, Self::Error>
+    where
+        I: AggregateId<A>,
+    {
+        self.with_store(|store| store.get_snapshot(id))
+    }
+}
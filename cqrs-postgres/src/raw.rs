@@ -1,23 +1,118 @@
 //! Types for interacting with raw event data in PostgreSQL event store.
 
 use crate::{error::LoadError, util::Sequence};
-use cqrs_core::{BorrowedRawEvent, RawEvent, Since};
+use cqrs_core::{
+    BorrowedRawEvent, EventUpcasters, GlobalEventSource, RawEvent, Since, UpcastError,
+};
 use fallible_iterator::FallibleIterator;
 use postgres::Connection;
+use std::borrow::Cow;
+use void::Void;
+
+/// The order in which events are read from the global event stream.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum GlobalOrdering {
+    /// Reads events in order of event id.
+    ///
+    /// Event ids are assigned when events are inserted, but concurrent transactions may commit in a different order, so
+    /// a reader that has moved past an event id may never see an event with a lower id that is committed later.
+    EventId,
+
+    /// Reads only events inserted by transactions that have finished, ordered by the id of the inserting transaction
+    /// and then by event id.
+    ///
+    /// No event is ever committed behind a position that has already been read, so readers never miss events.
+    /// Positions are still recorded as event ids. However, reads will not progress past events inserted by a
+    /// transaction while any older transaction on the database is still running, so long-running transactions delay
+    /// all readers.
+    ///
+    /// Requires database version 7.
+    GapSafe,
+}
+
+impl GlobalOrdering {
+    /// The condition selecting events after the event id given as the first query parameter.
+    pub(crate) fn after_condition(self) -> &'static str {
+        match self {
+            GlobalOrdering::EventId => "event_id > $1",
+            GlobalOrdering::GapSafe => {
+                "(transaction_id, event_id) > \
+                 (COALESCE((SELECT e.transaction_id FROM events e WHERE e.event_id = $1), 0), $1) \
+                 AND transaction_id < txid_snapshot_xmin(txid_current_snapshot())"
+            }
+        }
+    }
+
+    /// The ordering of events in the global event stream.
+    pub(crate) fn order_by(self) -> &'static str {
+        match self {
+            GlobalOrdering::EventId => "event_id ASC",
+            GlobalOrdering::GapSafe => "transaction_id ASC, event_id ASC",
+        }
+    }
+
+    fn read_query(self) -> String {
+        format!(
+            "SELECT event_id, aggregate_type, entity_id, sequence, event_type, \
+             COALESCE(binary_payload, convert_to(payload::text, 'UTF8')), schema_version, \
+             payload_format \
+             FROM events \
+             WHERE {} \
+             ORDER BY {} \
+             LIMIT $2",
+            self.after_condition(),
+            self.order_by(),
+        )
+    }
+}
+
+impl Default for GlobalOrdering {
+    #[inline]
+    fn default() -> Self {
+        GlobalOrdering::EventId
+    }
+}
 
 /// A connection to a PostgreSQL storage backend that is not specific to any aggregate.
+///
+/// Event payloads are returned in the [PayloadFormat](crate::PayloadFormat) in which they were persisted, as
+/// recorded in each event's `payload_format`.
 #[derive(Clone, Copy, Debug)]
 pub struct RawPostgresStore<'conn> {
     conn: &'conn Connection,
+    ordering: GlobalOrdering,
+    upcasters: Option<&'conn EventUpcasters>,
 }
 
 impl<'conn> RawPostgresStore<'conn> {
+    /// Constructs a raw store based on a provided PostgreSQL connection.
+    pub fn new(conn: &'conn Connection) -> Self {
+        RawPostgresStore {
+            conn,
+            ordering: GlobalOrdering::default(),
+            upcasters: None,
+        }
+    }
+
+    /// Sets the order in which events are read from the global event stream.
+    pub fn with_global_ordering(self, ordering: GlobalOrdering) -> Self {
+        RawPostgresStore { ordering, ..self }
+    }
+
+    /// Sets the upcasters applied to event payloads persisted with older schema versions before they are returned.
+    pub fn with_upcasters(self, upcasters: &'conn EventUpcasters) -> Self {
+        RawPostgresStore {
+            upcasters: Some(upcasters),
+            ..self
+        }
+    }
+
     /// Reads all events from the event stream, starting with events after `since`,
     pub fn read_all_events(
         self,
         since: Since,
         max_count: u64,
-    ) -> Result<Vec<RawEvent>, postgres::Error> {
+    ) -> Result<Vec<RawEvent>, LoadError<Void>> {
         let last_sequence = match since {
             Since::BeginningOfStream => 0,
             Since::Event(x) => x.get(),
@@ -32,8 +127,8 @@ impl<'conn> RawPostgresStore<'conn> {
             let aggregate_type = row.get(1);
             let entity_id = row.get(2);
             let sequence: Sequence = row.get(3);
-            let event_type = row.get(4);
-            let payload = row.get_bytes(5).unwrap();
+            let event_type: String = row.get(4);
+            let payload = self.upcast(&event_type, row.get(6), row.get_bytes(5).unwrap())?;
             log::trace!(
                 "entity {}/{}: loaded event; sequence: {}, type: {}",
                 aggregate_type,
@@ -41,25 +136,20 @@ impl<'conn> RawPostgresStore<'conn> {
                 sequence.0,
                 event_type,
             );
-            RawEvent {
+            Ok(RawEvent {
                 event_id: event_id.0,
                 aggregate_type,
                 entity_id,
                 sequence: sequence.0,
                 event_type,
-                payload: payload.to_owned(),
-            }
+                payload_format: row.get(7),
+                payload: payload.into_owned(),
+            })
         };
 
         let events: Vec<RawEvent>;
         {
-            let stmt = trans.prepare_cached(
-                "SELECT event_id, aggregate_type, entity_id, sequence, event_type, payload \
-                 FROM events \
-                 WHERE event_id > $1 \
-                 ORDER BY event_id ASC \
-                 LIMIT $2",
-            )?;
+            let stmt = trans.prepare_cached(&self.ordering.read_query())?;
             let rows = stmt.lazy_query(
                 &trans,
                 &[
@@ -71,8 +161,12 @@ impl<'conn> RawPostgresStore<'conn> {
 
             events = rows
                 .iterator()
-                .map(|row_result| row_result.map(handle_row))
-                .collect::<Result<_, postgres::Error>>()?;
+                .map(|row_result| {
+                    row_result
+                        .map_err(LoadError::from)
+                        .and_then(|row| handle_row(row))
+                })
+                .collect::<Result<_, LoadError<Void>>>()?;
         }
 
         trans.commit()?;
@@ -104,7 +198,8 @@ impl<'conn> RawPostgresStore<'conn> {
             let entity_id = std::str::from_utf8(row.get_bytes(2).unwrap()).unwrap();
             let sequence: Sequence = row.get(3);
             let event_type = std::str::from_utf8(row.get_bytes(4).unwrap()).unwrap();
-            let payload = row.get_bytes(5).unwrap();
+            let payload_format = std::str::from_utf8(row.get_bytes(7).unwrap()).unwrap();
+            let payload = self.upcast(event_type, row.get(6), row.get_bytes(5).unwrap())?;
             log::trace!(
                 "entity {}/{}: loaded event; sequence: {}, type: {}",
                 aggregate_type,
@@ -118,20 +213,15 @@ impl<'conn> RawPostgresStore<'conn> {
                 entity_id,
                 sequence: sequence.0,
                 event_type,
-                payload,
+                payload_format,
+                payload: &payload,
             })
             .map_err(LoadError::DeserializationError)
         };
 
         let events: Vec<()>;
         {
-            let stmt = trans.prepare_cached(
-                "SELECT event_id, aggregate_type, entity_id, sequence, event_type, payload \
-                 FROM events \
-                 WHERE event_id > $1 \
-                 ORDER BY event_id ASC \
-                 LIMIT $2",
-            )?;
+            let stmt = trans.prepare_cached(&self.ordering.read_query())?;
             let rows = stmt.lazy_query(
                 &trans,
                 &[
@@ -157,4 +247,54 @@ impl<'conn> RawPostgresStore<'conn> {
 
         Ok(())
     }
+
+    fn upcast<'a, E: cqrs_core::CqrsError>(
+        self,
+        event_type: &str,
+        schema_version: i64,
+        payload: &'a [u8],
+    ) -> Result<Cow<'a, [u8]>, LoadError<E>> {
+        match self.upcasters {
+            Some(upcasters) => upcast(upcasters, event_type, schema_version, payload)
+                .map_err(LoadError::UpcastError),
+            None => Ok(Cow::Borrowed(payload)),
+        }
+    }
+}
+
+/// Upcasts an event payload persisted with the given schema version to the latest schema version of its event type.
+///
+/// Returns an error if the schema version column holds a value that is not a valid schema version.
+pub(crate) fn upcast<'a>(
+    upcasters: &EventUpcasters,
+    event_type: &str,
+    schema_version: i64,
+    payload: &'a [u8],
+) -> Result<Cow<'a, [u8]>, UpcastError> {
+    if schema_version < 0 || schema_version > i64::from(u32::max_value()) {
+        return Err(UpcastError {
+            event_type: event_type.to_owned(),
+            schema_version: if schema_version < 0 {
+                0
+            } else {
+                u32::max_value()
+            },
+            reason: format!("schema version {} is out of range", schema_version),
+        });
+    }
+
+    upcasters.upcast(event_type, schema_version as u32, payload)
+}
+
+impl<'conn> GlobalEventSource for RawPostgresStore<'conn> {
+    type Error = LoadError<Void>;
+    type Events = Vec<RawEvent>;
+
+    fn read_all_events(
+        &self,
+        since: Since,
+        max_count: Option<u64>,
+    ) -> Result<Self::Events, Self::Error> {
+        RawPostgresStore::read_all_events(*self, since, max_count.unwrap_or(u64::max_value()))
+    }
 }
@@ -2,20 +2,40 @@
 //!
 //! Types for reacting to raw event data in PostgreSQL event store.
 
-use crate::db_wrapper::{DbConnection, DbPool, ReactorError};
+use crate::{db_wrapper::Listener, raw::GlobalOrdering};
+pub use crate::{
+    db_wrapper::{DbConnection, DbPool, ReactorError},
+    supervisor::{ReactionStatus, ReactionSupervisor, SupervisorError},
+};
 use cqrs_core::{
-    reactor::{AggregatePredicate, EventTypesPredicate, Reaction, ReactionPredicate},
-    CqrsError, RawEvent,
+    reactor::{
+        AggregatePredicate, EntityIdPredicate, EventTypesPredicate, Reaction, ReactionError,
+        ReactionPredicate, Reactor,
+    },
+    CqrsError, EventNumber, EventUpcasters, RawEvent,
 };
-use postgres::{rows::Rows, types::ToSql, Connection};
+use parking_lot::{Condvar, Mutex};
+use postgres::{transaction::Transaction, types::ToSql};
 use r2d2::Pool;
 use r2d2_postgres::PostgresConnectionManager;
 use std::{
     fmt::Write,
-    sync::atomic::{AtomicBool, Ordering},
-    time::Duration,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
 };
+use void::Void;
+
+/// The channel on which a notification is sent whenever an event is inserted. The payload is the aggregate type.
+pub const EVENTS_CHANNEL: &str = "events_inserted";
+
+/// The default maximum number of events read in a single batch.
+pub const DEFAULT_BATCH_SIZE: u64 = 100;
+
+/// How often a reactor waiting on [EVENTS_CHANNEL] checks whether it has been signalled to stop.
+const NOTIFY_STOP_CHECK_INTERVAL: Duration = Duration::from_millis(100);
 
+/// A reaction that ignores all events.
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
 pub struct NullReaction;
 
@@ -39,65 +59,789 @@ impl Reaction for NullReaction {
     }
 }
 
+/// A reaction whose side effects are written to the event store's database in the same transaction that records its
+/// progress, so that each event is reacted to exactly once.
+///
+/// If [TransactionalReaction::react] returns an error, the transaction is rolled back.
+pub trait TransactionalReaction {
+    /// The error type.
+    type Error: CqrsError;
+
+    /// The name under which the reaction's progress is recorded.
+    fn reaction_name() -> &'static str;
+
+    /// Reacts to an event within the given transaction.
+    fn react(&mut self, event: RawEvent, transaction: &Transaction) -> Result<(), Self::Error>;
+
+    /// The events to react to.
+    fn predicate(&self) -> ReactionPredicate;
+
+    /// The maximum amount of time to wait between batches once all events have been reacted to.
+    fn interval() -> Duration;
+}
+
+/// A reaction which reacts to a batch of events at a time, recording its progress once per batch.
+///
+/// If [BatchReaction::react_batch] returns an error, the reaction's progress is left at the end of the previous batch.
+pub trait BatchReaction {
+    /// The error type.
+    type Error: CqrsError;
+
+    /// The name under which the reaction's progress is recorded.
+    fn reaction_name() -> &'static str;
+
+    /// Reacts to a non-empty batch of events, in order of their event id.
+    fn react_batch(&mut self, events: Vec<RawEvent>) -> Result<(), Self::Error>;
+
+    /// The events to react to.
+    fn predicate(&self) -> ReactionPredicate;
+
+    /// The maximum amount of time to wait between batches once all events have been reacted to.
+    fn interval() -> Duration;
+}
+
+/// How a reactor waits for new events once it has caught up.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum WakeStrategy {
+    /// Sleeps for [Reaction::interval] between batches.
+    Poll,
+
+    /// Listens on [EVENTS_CHANNEL] and wakes as soon as new events are inserted, waiting at most
+    /// [Reaction::interval] between batches.
+    Notify,
+}
+
+impl Default for WakeStrategy {
+    fn default() -> Self {
+        WakeStrategy::Poll
+    }
+}
+
+/// What a reactor does when a reaction returns an error.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ErrorPolicy {
+    /// Stops the reaction, returning the error.
+    Stop,
+
+    /// Retries reacting to the event up to `max_retries` times, doubling the delay between each attempt up to
+    /// `max_backoff`. If the reaction still fails, or the reactor is stopped while waiting to retry, it is stopped and
+    /// the error is returned.
+    Retry {
+        /// The maximum number of times to retry.
+        max_retries: u32,
+
+        /// The delay before the first retry.
+        initial_backoff: Duration,
+
+        /// The longest delay between retries.
+        max_backoff: Duration,
+    },
+
+    /// Logs the error and moves past the event.
+    ///
+    /// If a [BatchReaction] fails, each event in the batch is reacted to again in a batch of its own, so that only the
+    /// events which fail are skipped.
+    Skip,
+
+    /// Records the event and the error in the `reaction_dead_letters` table and moves past the event.
+    ///
+    /// If a [BatchReaction] fails, each event in the batch is reacted to again in a batch of its own, so that only the
+    /// events which fail are dead-lettered. Dead letters which have been requeued with
+    /// [PostgresReactor::requeue_dead_letters] are reacted to again before each batch of new events.
+    DeadLetter,
+}
+
+impl Default for ErrorPolicy {
+    fn default() -> Self {
+        ErrorPolicy::Stop
+    }
+}
+
+impl ErrorPolicy {
+    /// The delay before the given retry, counting from zero.
+    fn backoff(self, retry: u32) -> Duration {
+        match self {
+            ErrorPolicy::Retry {
+                initial_backoff,
+                max_backoff,
+                ..
+            } => initial_backoff
+                .checked_mul(1 << retry.min(16))
+                .map_or(max_backoff, |backoff| backoff.min(max_backoff)),
+            _ => Duration::default(),
+        }
+    }
+}
+
+/// Lease-based ownership of a reaction, so that only one reactor runs a given reaction at a time.
+///
+/// Before each batch, and again before reacting to each unit of events, the reactor acquires or renews the lease in the
+/// `reaction_leases` table. While another owner holds an unexpired lease, the reactor waits on standby, taking over once
+/// the lease lapses. The reactor only saves a reaction's position while it holds the lease, so a reactor whose lease has
+/// lapsed goes back on standby instead of moving the position of a reaction that another owner has taken over.
+///
+/// The lease duration should comfortably exceed the time taken to react to a unit of events. Retry backoff under
+/// [ErrorPolicy::Retry] is capped at half the lease duration. The lease is released when the reaction stops, whether or
+/// not it stops with an error.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Lease {
+    /// Identifies the reactor holding the lease, and must be unique to each node.
+    pub owner: String,
+
+    /// How long the lease lasts after each renewal.
+    pub duration: Duration,
+}
+
+/// One of a fixed number of partitions of a reaction, so that a reaction can be sharded across many reactors.
+///
+/// Events are assigned to partitions by a stable hash of their entity id, so all events for an entity are reacted to,
+/// in order, by the same partition. Each partition records its own position, lease and dead letters under the name
+/// given by [Partition::checkpoint_name]. Changing the number of partitions starts the new partitions from the
+/// beginning of the event stream.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Partition {
+    index: u32,
+    count: u32,
+}
+
+impl Partition {
+    /// Constructs the partition with the given zero-based index, out of `count` partitions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not less than `count`.
+    pub fn new(index: u32, count: u32) -> Self {
+        assert!(
+            index < count,
+            "partition index {} out of range for {} partitions",
+            index,
+            count
+        );
+        Partition { index, count }
+    }
+
+    /// Gets every partition of a reaction split into `count` partitions.
+    pub fn all(count: u32) -> impl Iterator<Item = Partition> {
+        (0..count).map(move |index| Partition { index, count })
+    }
+
+    /// The zero-based index of the partition.
+    pub fn index(self) -> u32 {
+        self.index
+    }
+
+    /// The total number of partitions.
+    pub fn count(self) -> u32 {
+        self.count
+    }
+
+    /// The name under which the partition of the named reaction records its position, lease and dead letters.
+    pub fn checkpoint_name(self, reaction_name: &str) -> String {
+        format!("{}[{}/{}]", reaction_name, self.index, self.count)
+    }
+}
+
+/// An event that a reaction failed to react to.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct DeadLetter {
+    /// The name of the reaction.
+    pub reaction_name: String,
+
+    /// The event.
+    pub event: RawEvent,
+
+    /// The error returned by the reaction, as text.
+    pub error: String,
+
+    /// Whether the event has been requeued to be reacted to again.
+    pub requeued: bool,
+}
+
+/// The position of a reaction in the event stream.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct ReactionPosition {
+    /// The name of the reaction.
+    pub reaction_name: String,
+
+    /// The id of the last event reacted to.
+    pub event_id: EventNumber,
+
+    /// The difference between the id of the latest event in the store and [ReactionPosition::event_id].
+    ///
+    /// This is a distance between event ids rather than a count of the events left for the reaction to react to: it
+    /// includes events that the reaction's predicate filters out, and gaps in the event id sequence left by rolled-back
+    /// transactions, and it takes no account of the order in which the reactor reads events. It is zero once the
+    /// reaction has reacted to the latest event.
+    pub event_id_distance: u64,
+}
+
+/// A reactor which feeds events from the PostgreSQL event store to reactions, recording their progress in the
+/// `reactions` table.
 #[derive(Debug)]
 pub struct PostgresReactor<P = Pool<PostgresConnectionManager>> {
     pool: P,
-    run: AtomicBool,
+    stop_count: Mutex<u64>,
+    stop_signal: Condvar,
+    wake_strategy: WakeStrategy,
+    batch_size: u64,
+    error_policy: ErrorPolicy,
+    lease: Option<Lease>,
+    partition: Option<Partition>,
+    global_ordering: GlobalOrdering,
+    upcasters: Arc<EventUpcasters>,
+}
+
+impl<P> PostgresReactor<P> {
+    /// Signals any running reactions to stop after their current batch, interrupting any wait for new events.
+    ///
+    /// Reactions started afterwards run until the reactor is stopped again.
+    pub fn stop_reaction(&self) {
+        let mut stop_count = self.stop_count.lock();
+        *stop_count = stop_count.wrapping_add(1);
+        self.stop_signal.notify_all();
+    }
+
+    /// The number of times the reactor has been stopped. A reaction runs until this changes from its value when the
+    /// reaction was started.
+    pub(crate) fn stop_count(&self) -> u64 {
+        *self.stop_count.lock()
+    }
+
+    fn is_running(&self, started: u64) -> bool {
+        self.stop_count() == started
+    }
+
+    /// Sleeps for `duration`, or until signalled to stop a reaction started at `started`.
+    fn sleep(&self, duration: Duration, started: u64) {
+        let deadline = Instant::now() + duration;
+        let mut stops = self.stop_count.lock();
+        while *stops == started {
+            if self
+                .stop_signal
+                .wait_until(&mut stops, deadline)
+                .timed_out()
+            {
+                break;
+            }
+        }
+    }
+}
+
+/// A handle to a reaction running on its own thread.
+#[derive(Debug)]
+pub struct ReactionHandle<P, T> {
+    reactor: Arc<PostgresReactor<P>>,
+    thread: thread::JoinHandle<T>,
+}
+
+impl<P, T> ReactionHandle<P, T> {
+    /// Signals the reaction to stop after its current batch, interrupting any wait for new events.
+    pub fn stop(&self) {
+        self.reactor.stop_reaction();
+    }
+
+    /// Waits for the reaction to stop, returning the number of events reacted to or the error that stopped it.
+    ///
+    /// As with [std::thread::JoinHandle::join], an error is returned if the reaction panicked.
+    pub fn join(self) -> thread::Result<T> {
+        self.thread.join()
+    }
+
+    /// Signals the reaction to stop, and waits for it to finish its current batch.
+    pub fn stop_and_join(self) -> thread::Result<T> {
+        self.stop();
+        self.join()
+    }
 }
 
 impl<P> PostgresReactor<P>
 where
     P: for<'conn> DbPool<'conn>,
 {
+    /// Constructs a new reactor, polling for new events.
     pub fn new(pool: P) -> Self {
         Self {
             pool,
-            run: AtomicBool::new(true),
+            stop_count: Mutex::default(),
+            stop_signal: Condvar::new(),
+            wake_strategy: WakeStrategy::default(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            error_policy: ErrorPolicy::default(),
+            lease: None,
+            partition: None,
+            global_ordering: GlobalOrdering::default(),
+            upcasters: Arc::default(),
         }
     }
 
-    pub fn stop_reaction(&self) {
-        self.run.store(false, Ordering::Relaxed);
+    /// Sets how the reactor waits for new events.
+    pub fn with_wake_strategy(self, wake_strategy: WakeStrategy) -> Self {
+        Self {
+            wake_strategy,
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of events read in a single batch.
+    pub fn with_batch_size(self, batch_size: u64) -> Self {
+        Self {
+            batch_size: batch_size.max(1),
+            ..self
+        }
+    }
+
+    /// Sets what the reactor does when a reaction returns an error.
+    pub fn with_error_policy(self, error_policy: ErrorPolicy) -> Self {
+        Self {
+            error_policy,
+            ..self
+        }
+    }
+
+    /// Only runs reactions while holding a lease on them, so that the same reaction can safely be run on many nodes.
+    pub fn with_lease(self, lease: Lease) -> Self {
+        Self {
+            lease: Some(lease),
+            ..self
+        }
+    }
+
+    /// Only reacts to events in the given partition, so that a reaction can be sharded across many reactors.
+    pub fn with_partition(self, partition: Partition) -> Self {
+        Self {
+            partition: Some(partition),
+            ..self
+        }
+    }
+
+    /// Sets the order in which events are read from the event store.
+    ///
+    /// Use [GlobalOrdering::GapSafe] so that reactions never miss events committed out of order by concurrent
+    /// transactions.
+    pub fn with_global_ordering(self, global_ordering: GlobalOrdering) -> Self {
+        Self {
+            global_ordering,
+            ..self
+        }
+    }
+
+    /// Sets the upcasters applied to event payloads persisted with older schema versions before they are fed to
+    /// reactions.
+    pub fn with_upcasters(self, upcasters: impl Into<Arc<EventUpcasters>>) -> Self {
+        Self {
+            upcasters: upcasters.into(),
+            ..self
+        }
+    }
+
+    /// Lists the position of every reaction that has reacted to an event, and its distance from the latest event id, in
+    /// order of reaction name.
+    pub fn reactions(
+        &self,
+    ) -> Result<Vec<ReactionPosition>, ReactorError<Void, impl CqrsError, impl CqrsError>> {
+        let conn = self.pool.get().map_err(ReactorError::pool)?;
+        conn.read_reaction_positions()
+            .map_err(ReactorError::postgres)
+    }
+
+    /// Resets a reaction, so that it next reacts from the beginning of the event stream.
+    ///
+    /// The reaction should be stopped first, as a running reaction will go on saving its position. A reactor with a
+    /// [Partition] resets the position of its own partition of the reaction.
+    pub fn reset_reaction(
+        &self,
+        reaction_name: &str,
+    ) -> Result<(), ReactorError<Void, impl CqrsError, impl CqrsError>> {
+        let conn = self.pool.get().map_err(ReactorError::pool)?;
+        conn.delete_since(&self.checkpoint_name(reaction_name))
+            .map_err(ReactorError::postgres)
+    }
+
+    /// Rewinds a reaction, so that it next reacts to the events after `event_id`.
+    ///
+    /// The reaction should be stopped first, as a running reaction will go on saving its position. A reactor with a
+    /// [Partition] rewinds the position of its own partition of the reaction.
+    pub fn rewind_reaction(
+        &self,
+        reaction_name: &str,
+        event_id: EventNumber,
+    ) -> Result<(), ReactorError<Void, impl CqrsError, impl CqrsError>> {
+        let conn = self.pool.get().map_err(ReactorError::pool)?;
+        conn.save_since(&self.checkpoint_name(reaction_name), event_id, None)
+            .map(|_| ())
+            .map_err(ReactorError::postgres)
     }
 
+    /// Lists the dead letters recorded for a reaction, in order of event id.
+    ///
+    /// The dead letters of a partitioned reaction are recorded under its [Partition::checkpoint_name].
+    pub fn dead_letters(
+        &self,
+        reaction_name: &str,
+    ) -> Result<Vec<DeadLetter>, ReactorError<Void, impl CqrsError, impl CqrsError>> {
+        let conn = self.pool.get().map_err(ReactorError::pool)?;
+        conn.read_dead_letters(reaction_name, false, &self.upcasters)
+            .map_err(ReactorError::postgres)?
+            .map_err(ReactorError::upcast)
+    }
+
+    /// Requeues dead letters for a reaction to be reacted to again, returning the number of dead letters requeued.
+    ///
+    /// Requeued dead letters are only reacted to by a reactor using [ErrorPolicy::DeadLetter].
+    pub fn requeue_dead_letters(
+        &self,
+        reaction_name: &str,
+        event_ids: &[EventNumber],
+    ) -> Result<u64, ReactorError<Void, impl CqrsError, impl CqrsError>> {
+        let conn = self.pool.get().map_err(ReactorError::pool)?;
+        conn.requeue_dead_letters(reaction_name, event_ids)
+            .map_err(ReactorError::postgres)
+    }
+
+    /// Runs the reaction until [PostgresReactor::stop_reaction] is called, returning the number of events reacted to.
     pub fn start_reaction<R: Reaction>(
+        &self,
+        reaction: R,
+    ) -> Result<usize, ReactorError<R::Error, impl CqrsError, impl CqrsError>> {
+        self.run_reaction(NonTransactional(reaction), self.stop_count())
+    }
+
+    /// Runs the transactional reaction until [PostgresReactor::stop_reaction] is called, returning the number of events
+    /// reacted to.
+    pub fn start_transactional_reaction<R: TransactionalReaction>(
+        &self,
+        reaction: R,
+    ) -> Result<usize, ReactorError<R::Error, impl CqrsError, impl CqrsError>> {
+        self.run_reaction(Transactional(reaction), self.stop_count())
+    }
+
+    /// Runs the batch reaction until [PostgresReactor::stop_reaction] is called, returning the number of events
+    /// reacted to.
+    pub fn start_batch_reaction<R: BatchReaction>(
+        &self,
+        reaction: R,
+    ) -> Result<usize, ReactorError<R::Error, impl CqrsError, impl CqrsError>> {
+        self.run_reaction(Batch(reaction), self.stop_count())
+    }
+
+    /// Runs the reaction on a new thread until stopped through the returned handle.
+    pub fn spawn_reaction<R>(
+        self,
+        reaction: R,
+    ) -> ReactionHandle<P, Result<usize, ReactorError<R::Error, impl CqrsError, impl CqrsError>>>
+    where
+        P: Send + Sync + 'static,
+        R: Reaction + Send + 'static,
+    {
+        self.spawn(NonTransactional(reaction))
+    }
+
+    /// Runs the transactional reaction on a new thread until stopped through the returned handle.
+    pub fn spawn_transactional_reaction<R>(
+        self,
+        reaction: R,
+    ) -> ReactionHandle<P, Result<usize, ReactorError<R::Error, impl CqrsError, impl CqrsError>>>
+    where
+        P: Send + Sync + 'static,
+        R: TransactionalReaction + Send + 'static,
+    {
+        self.spawn(Transactional(reaction))
+    }
+
+    /// Runs the batch reaction on a new thread until stopped through the returned handle.
+    pub fn spawn_batch_reaction<R>(
+        self,
+        reaction: R,
+    ) -> ReactionHandle<P, Result<usize, ReactorError<R::Error, impl CqrsError, impl CqrsError>>>
+    where
+        P: Send + Sync + 'static,
+        R: BatchReaction + Send + 'static,
+    {
+        self.spawn(Batch(reaction))
+    }
+
+    fn spawn<R>(
+        self,
+        reaction: R,
+    ) -> ReactionHandle<P, Result<usize, ReactorError<R::Error, impl CqrsError, impl CqrsError>>>
+    where
+        P: Send + Sync + 'static,
+        R: ReactionStep + Send + 'static,
+    {
+        // Start the reaction before spawning its thread, so that a stop through the handle is never missed.
+        let started = self.stop_count();
+        let reactor = Arc::new(self);
+        let thread_reactor = Arc::clone(&reactor);
+        let thread = thread::spawn(move || thread_reactor.run_reaction(reaction, started));
+
+        ReactionHandle { reactor, thread }
+    }
+
+    /// Runs the reaction until the reactor is stopped after `started`, a value of [PostgresReactor::stop_count] taken
+    /// when the reaction was started.
+    pub(crate) fn run_reaction<R: ReactionStep>(
         &self,
         mut reaction: R,
-    ) -> Result<usize, ReactorError<R, impl CqrsError, impl CqrsError>> {
+        started: u64,
+    ) -> Result<usize, ReactorError<R::Error, impl CqrsError, impl CqrsError>> {
         let mut event_count = usize::default();
+        let name = self.checkpoint_name(R::reaction_name());
+        let _lease_guard = self.lease.as_ref().map(|lease| LeaseGuard {
+            pool: &self.pool,
+            name: &name,
+            owner: &lease.owner,
+        });
+
+        let listener = match self.wake_strategy {
+            WakeStrategy::Poll => None,
+            WakeStrategy::Notify => {
+                let conn = self.pool.get().map_err(ReactorError::pool)?;
+                Some(Listener::listen(conn, EVENTS_CHANNEL).map_err(ReactorError::postgres)?)
+            }
+        };
 
-        while self.run.load(Ordering::Relaxed) {
+        'run: while self.is_running(started) {
             let conn = self.pool.get().map_err(ReactorError::pool)?;
-            let since = conn
-                .load_since(R::reaction_name())
-                .map_err(ReactorError::postgres)?;
+
+            match self.hold_lease(&conn, &name) {
+                Ok(()) => {}
+                Err(StepError::Connection(err)) => return Err(ReactorError::postgres(err)),
+                Err(StepError::LeaseLost) => {
+                    log::trace!("reaction {}: lease held by another owner; waiting", name);
+                    drop(conn);
+                    self.sleep(R::interval(), started);
+                    continue;
+                }
+            }
+
+            if self.error_policy == ErrorPolicy::DeadLetter {
+                let requeued = conn
+                    .read_dead_letters(&name, true, &self.upcasters)
+                    .map_err(ReactorError::postgres)?
+                    .map_err(ReactorError::upcast)?;
+                for dead_letter in requeued {
+                    let events = vec![dead_letter.event];
+                    match self.react_with_policy(&mut reaction, &name, &conn, events, true, started)
+                    {
+                        Ok(reacted) => event_count += reacted.map_err(ReactorError::react)?,
+                        Err(StepError::Connection(err)) => return Err(ReactorError::postgres(err)),
+                        Err(StepError::LeaseLost) => {
+                            log::warn!("reaction {}: lease lost; going on standby", name);
+                            continue 'run;
+                        }
+                    }
+                }
+            }
+
+            let since = conn.load_since(&name).map_err(ReactorError::postgres)?;
             let mut params: Vec<Box<dyn ToSql>> = Vec::default();
             let query_with_args =
-                self.generate_query_with_args::<R>(reaction.predicate(), &mut params, 100);
+                self.generate_query_with_args(reaction.predicate(), &mut params, self.batch_size);
 
             let raw_events = conn
-                .read_all_events(&query_with_args, since, params.as_slice())
-                .map_err(ReactorError::postgres)?;
-
-            for event in raw_events {
-                let event_id = event.event_id;
-                reaction.react(event).map_err(ReactorError::react)?;
+                .read_all_events(&query_with_args, since, params.as_slice(), &self.upcasters)
+                .map_err(ReactorError::postgres)?
+                .map_err(ReactorError::upcast)?;
+            let batch_full = raw_events.len() as u64 >= self.batch_size;
+
+            for unit in R::units(raw_events) {
+                match self.react_with_policy(&mut reaction, &name, &conn, unit, false, started) {
+                    Ok(reacted) => event_count += reacted.map_err(ReactorError::react)?,
+                    Err(StepError::Connection(err)) => return Err(ReactorError::postgres(err)),
+                    Err(StepError::LeaseLost) => {
+                        log::warn!("reaction {}: lease lost; going on standby", name);
+                        continue 'run;
+                    }
+                }
+            }
 
-                conn.save_since(R::reaction_name(), event_id)
-                    .map_err(ReactorError::postgres)?;
+            drop(conn);
 
-                event_count += 1;
+            if batch_full {
+                continue;
             }
 
-            drop(conn);
+            match listener {
+                Some(ref listener) => {
+                    let deadline = Instant::now() + R::interval();
+                    while self.is_running(started) {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            break;
+                        }
 
-            ::std::thread::sleep(R::interval());
+                        let timeout = (deadline - now).min(NOTIFY_STOP_CHECK_INTERVAL);
+                        if listener.wait(timeout).map_err(ReactorError::postgres)? {
+                            break;
+                        }
+                    }
+                }
+                None => self.sleep(R::interval(), started),
+            }
         }
 
         Ok(event_count)
     }
 
-    fn generate_query_with_args<R: Reaction>(
+    /// Acquires or renews the lease on a reaction, if the reactor uses one.
+    fn hold_lease<'conn, C>(&self, conn: &C, name: &str) -> Result<(), StepError<C::Error>>
+    where
+        C: DbConnection<'conn>,
+    {
+        if let Some(ref lease) = self.lease {
+            if !conn.acquire_lease(name, &lease.owner, lease.duration)? {
+                return Err(StepError::LeaseLost);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The delay before the given retry, capped at half the lease duration so that the lease outlasts the wait.
+    fn retry_backoff(&self, retry: u32) -> Duration {
+        let backoff = self.error_policy.backoff(retry);
+        match self.lease {
+            Some(ref lease) => backoff.min(lease.duration / 2),
+            None => backoff,
+        }
+    }
+
+    /// Reacts to a unit of events, applying the error policy if the reaction fails. Returns the number of events
+    /// reacted to, or an error if the reaction should be stopped.
+    ///
+    /// The lease, if any, is renewed before each attempt, and [StepError::LeaseLost] is returned if it has been taken
+    /// over by another owner.
+    ///
+    /// If a unit of several events fails under [ErrorPolicy::Skip] or [ErrorPolicy::DeadLetter], each of its events is
+    /// reacted to again as a unit of its own, so that only the events which fail are skipped or dead-lettered.
+    ///
+    /// Requeued dead letters are removed once reacted to, and do not move the reaction's position.
+    fn react_with_policy<'conn, R, C>(
+        &self,
+        reaction: &mut R,
+        name: &str,
+        conn: &C,
+        events: Vec<RawEvent>,
+        requeued: bool,
+        started: u64,
+    ) -> Result<Result<usize, R::Error>, StepError<C::Error>>
+    where
+        R: ReactionStep,
+        C: DbConnection<'conn>,
+    {
+        let checkpoint = if requeued { None } else { Some(name) };
+        let lease_owner = self.lease.as_ref().map(|lease| lease.owner.as_str());
+        let event_count = events.len();
+        let max_retries = match self.error_policy {
+            ErrorPolicy::Stop => {
+                self.hold_lease(conn, name)?;
+                return Ok(reaction
+                    .react_and_save(conn, events, checkpoint, lease_owner)?
+                    .map(|()| event_count));
+            }
+            ErrorPolicy::Retry { max_retries, .. } => max_retries,
+            ErrorPolicy::Skip | ErrorPolicy::DeadLetter => 0,
+        };
+
+        let mut retries = 0;
+        let err = loop {
+            self.hold_lease(conn, name)?;
+            match reaction.react_and_save(conn, events.clone(), checkpoint, lease_owner)? {
+                Ok(()) => {
+                    if requeued {
+                        for event in &events {
+                            conn.delete_dead_letter(name, event.event_id)?;
+                        }
+                    }
+                    return Ok(Ok(event_count));
+                }
+                Err(err) if retries < max_retries => {
+                    let backoff = self.retry_backoff(retries);
+                    log::warn!(
+                        "reaction {}: retrying in {:?} after error: {}",
+                        name,
+                        backoff,
+                        err
+                    );
+                    self.sleep(backoff, started);
+                    if !self.is_running(started) {
+                        log::warn!("reaction {}: stopped while retrying", name);
+                        break err;
+                    }
+                    retries += 1;
+                }
+                Err(err) => break err,
+            }
+        };
+
+        match self.error_policy {
+            ErrorPolicy::Stop | ErrorPolicy::Retry { .. } => return Ok(Err(err)),
+            ErrorPolicy::Skip | ErrorPolicy::DeadLetter if event_count > 1 => {
+                log::warn!(
+                    "reaction {}: reacting to {} events one at a time after error: {}",
+                    name,
+                    event_count,
+                    err
+                );
+
+                let mut reacted_count = 0;
+                for event in events {
+                    match self.react_with_policy(
+                        reaction,
+                        name,
+                        conn,
+                        vec![event],
+                        requeued,
+                        started,
+                    )? {
+                        Ok(reacted) => reacted_count += reacted,
+                        Err(err) => return Ok(Err(err)),
+                    }
+                }
+                return Ok(Ok(reacted_count));
+            }
+            ErrorPolicy::Skip | ErrorPolicy::DeadLetter => {}
+        }
+
+        let event = match events.first() {
+            Some(event) => event,
+            None => return Ok(Ok(0)),
+        };
+
+        if self.error_policy == ErrorPolicy::DeadLetter {
+            conn.save_dead_letter(name, event.event_id, &err.to_string())?;
+            log::warn!(
+                "reaction {}: dead-lettered event {} after error: {}",
+                name,
+                event.event_id,
+                err
+            );
+        } else {
+            log::warn!(
+                "reaction {}: skipping event {} after error: {}",
+                name,
+                event.event_id,
+                err
+            );
+        }
+
+        if let Some(name) = checkpoint {
+            if !conn.save_since(name, event.event_id, lease_owner)? {
+                return Err(StepError::LeaseLost);
+            }
+        }
+
+        Ok(Ok(0))
+    }
+
+    fn checkpoint_name(&self, reaction_name: &str) -> String {
+        match self.partition {
+            Some(partition) => partition.checkpoint_name(reaction_name),
+            None => reaction_name.to_owned(),
+        }
+    }
+
+    fn generate_query_with_args(
         &self,
         predicate: ReactionPredicate,
         params: &mut Vec<Box<dyn ToSql>>,
@@ -105,120 +849,411 @@ where
     ) -> String {
         let max_count = Box::new(max_count.min(i64::max_value() as u64) as i64);
 
-        match predicate.aggregate_predicate {
-            AggregatePredicate::AllAggregates(EventTypesPredicate::AllEventTypes) => {
-                params.push(max_count);
+        let mut query = format!(
+            "SELECT event_id, aggregate_type, entity_id, sequence, event_type, \
+             COALESCE(binary_payload, convert_to(payload::text, 'UTF8')), schema_version, \
+             payload_format \
+             FROM events \
+             WHERE {}",
+            self.global_ordering.after_condition(),
+        );
 
-                String::from(
-                    "SELECT event_id, aggregate_type, entity_id, sequence, event_type, payload \
-                     FROM events \
-                     WHERE event_id > $1 \
-                     ORDER BY event_id ASC \
-                     LIMIT $2",
-                )
-            }
+        match predicate.aggregate_predicate {
+            AggregatePredicate::AllAggregates(EventTypesPredicate::AllEventTypes) => {}
             AggregatePredicate::AllAggregates(EventTypesPredicate::SpecificEventTypes(
                 event_types,
             )) => {
                 params.push(Box::new(event_types));
-                params.push(max_count);
 
-                String::from(
-                    "SELECT event_id, aggregate_type, entity_id, sequence, event_type, payload \
-                     FROM events \
-                     WHERE event_id > $1 \
-                     AND event_type = ANY ($2) \
-                     ORDER BY event_id ASC \
-                     LIMIT $3",
-                )
+                write!(query, " AND event_type = ANY (${})", params.len() + 1)
+                    .expect("Formatting integers into a string never fails");
             }
             AggregatePredicate::SpecificAggregates(aggregate_predicates) => {
-                let mut query = String::from(
-                    "SELECT event_id, aggregate_type, entity_id, sequence, event_type, payload \
-                     FROM events \
-                     WHERE event_id > $1 AND (FALSE",
-                );
-
-                let mut param_count = 1;
+                query.push_str(" AND (FALSE");
 
                 for predicate in aggregate_predicates {
-                    match &predicate.event_types {
+                    match predicate.event_types {
                         EventTypesPredicate::SpecificEventTypes(event_types) => {
                             write!(
                                 query,
                                 " OR (aggregate_type = ${} AND event_type = ANY (${}))",
-                                param_count + 1,
-                                param_count + 2
+                                params.len() + 2,
+                                params.len() + 3
                             )
                             .expect("Formatting integers into a string never fails");
 
                             params.push(Box::new(predicate.aggregate_type));
                             params.push(Box::new(event_types));
-                            param_count += 2;
                         }
                         EventTypesPredicate::AllEventTypes => {
-                            write!(query, " OR (aggregate_type = ${})", param_count + 1)
+                            write!(query, " OR (aggregate_type = ${})", params.len() + 2)
                                 .expect("Formatting integers into a string never fails");
 
                             params.push(Box::new(predicate.aggregate_type));
-                            param_count += 1;
                         }
                     }
                 }
 
-                write!(query, ") ORDER BY event_id ASC LIMIT ${}", param_count + 1)
+                query.push(')');
+            }
+        }
+
+        match predicate.entity_id_predicate {
+            EntityIdPredicate::AllEntities => {}
+            EntityIdPredicate::SpecificEntities(entity_ids) => {
+                params.push(Box::new(entity_ids));
+
+                write!(query, " AND entity_id = ANY (${})", params.len() + 1)
                     .expect("Formatting integers into a string never fails");
+            }
+            EntityIdPredicate::MatchingPattern(pattern) => {
+                params.push(Box::new(pattern));
 
-                params.push(max_count);
-                query
+                write!(query, " AND entity_id LIKE ${}", params.len() + 1)
+                    .expect("Formatting integers into a string never fails");
             }
         }
-    }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::{
-        db_wrapper::{DbConnection, DbPool, ReactorError},
-        reactor::{self, NullReaction, PostgresReactor},
-    };
-    use cqrs_core::{
-        reactor::{
-            AggregatePredicate, EventTypesPredicate, Reaction, ReactionPredicate,
-            SpecificAggregatePredicate,
-        },
-        CqrsError, EventNumber, RawEvent, Since,
-    };
-    use lazy_static::lazy_static;
-    use parking_lot::Mutex;
-    use postgres::{error, types::ToSql, Connection};
-    use r2d2_postgres::{r2d2::Pool, PostgresConnectionManager, TlsMode};
-    use std::{
-        io::{self, Error},
-        sync::Arc,
-        thread,
-        time::Duration,
-    };
+        for metadata_predicate in predicate.metadata_predicates {
+            write!(
+                query,
+                " AND metadata->>${} = ${}",
+                params.len() + 2,
+                params.len() + 3
+            )
+            .expect("Formatting integers into a string never fails");
+
+            params.push(Box::new(metadata_predicate.field));
+            params.push(Box::new(metadata_predicate.value));
+        }
 
-    lazy_static! {
-        static ref PREDICATE: Mutex<ReactionPredicate> = Mutex::new(ReactionPredicate::default());
-        static ref RAW_EVENT: RawEvent = RawEvent {
-            event_id: EventNumber::new(1).unwrap(),
-            aggregate_type: String::from(""),
-            entity_id: String::from(""),
-            sequence: EventNumber::new(1).unwrap(),
-            event_type: String::from(""),
-            payload: Vec::from("{}"),
-        };
-        static ref RAW_EVENTS: Vec<RawEvent> = vec![RAW_EVENT.clone(), RAW_EVENT.clone(),];
-    }
+        if let Some(partition) = self.partition {
+            // The first 60 bits of the MD5 hash of the entity id, as a non-negative bigint.
+            write!(
+                query,
+                " AND mod(('x' || substr(md5(entity_id), 1, 15))::bit(60)::bigint, ${}) = ${}",
+                params.len() + 2,
+                params.len() + 3
+            )
+            .expect("Formatting integers into a string never fails");
+
+            params.push(Box::new(i64::from(partition.count)));
+            params.push(Box::new(i64::from(partition.index)));
+        }
 
-    #[derive(Debug)]
-    pub struct MockPool {
-        get_result: Result<MockConnection, String>,
+        write!(
+            query,
+            " ORDER BY {} LIMIT ${}",
+            self.global_ordering.order_by(),
+            params.len() + 2
+        )
+        .expect("Formatting integers into a string never fails");
+
+        params.push(max_count);
+        query
     }
+}
 
-    impl<'conn> DbPool<'conn> for MockPool {
+impl<P> Reactor for PostgresReactor<P>
+where
+    P: for<'conn> DbPool<'conn>,
+{
+    type Error = ReactorError<Void, dyn CqrsError, dyn CqrsError>;
+
+    fn start_reaction<R: Reaction>(
+        &self,
+        reaction: R,
+    ) -> Result<usize, ReactionError<Self::Error, R::Error>> {
+        PostgresReactor::start_reaction(self, reaction).map_err(|err| match err {
+            ReactorError::Pool(err) => {
+                let err: Arc<dyn CqrsError> = err;
+                ReactionError::Reactor(ReactorError::Pool(err))
+            }
+            ReactorError::Postgres(err) => {
+                let err: Arc<dyn CqrsError> = err;
+                ReactionError::Reactor(ReactorError::Postgres(err))
+            }
+            ReactorError::React(err) => ReactionError::React(err),
+            ReactorError::Upcast(err) => ReactionError::Reactor(ReactorError::Upcast(err)),
+        })
+    }
+
+    fn stop_reaction(&self) {
+        PostgresReactor::stop_reaction(self)
+    }
+}
+
+/// A single step of a reaction: reacting to a unit of events and saving the reaction's position.
+pub(crate) trait ReactionStep {
+    type Error: CqrsError;
+
+    fn reaction_name() -> &'static str;
+    fn predicate(&self) -> ReactionPredicate;
+    fn interval() -> Duration;
+
+    /// Splits a batch of events into the units that are reacted to, and fail, together.
+    fn units(events: Vec<RawEvent>) -> Vec<Vec<RawEvent>> {
+        events.into_iter().map(|event| vec![event]).collect()
+    }
+
+    /// Reacts to a unit of events, saving the reaction's position under `checkpoint`, if given, while `lease_owner`, if
+    /// given, holds the lease.
+    fn react_and_save<'conn, C>(
+        &mut self,
+        conn: &C,
+        events: Vec<RawEvent>,
+        checkpoint: Option<&str>,
+        lease_owner: Option<&str>,
+    ) -> Result<Result<(), Self::Error>, StepError<C::Error>>
+    where
+        C: DbConnection<'conn>;
+}
+
+/// An error which interrupts a step of a reaction.
+pub(crate) enum StepError<E> {
+    /// An error from the database connection.
+    Connection(E),
+
+    /// The lease on the reaction was taken over by another owner, so the reaction's position was not saved.
+    LeaseLost,
+}
+
+impl<E> From<E> for StepError<E> {
+    fn from(err: E) -> Self {
+        StepError::Connection(err)
+    }
+}
+
+/// Releases a reactor's lease on a reaction when dropped, so that the lease is released however the reaction stops.
+struct LeaseGuard<'a, P>
+where
+    P: for<'conn> DbPool<'conn>,
+{
+    pool: &'a P,
+    name: &'a str,
+    owner: &'a str,
+}
+
+impl<'a, P> Drop for LeaseGuard<'a, P>
+where
+    P: for<'conn> DbPool<'conn>,
+{
+    fn drop(&mut self) {
+        let released = match self.pool.get() {
+            Ok(conn) => conn
+                .release_lease(self.name, self.owner)
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+
+        if let Err(err) = released {
+            log::warn!("reaction {}: failed to release lease: {}", self.name, err);
+        }
+    }
+}
+
+pub(crate) struct NonTransactional<R>(pub(crate) R);
+
+impl<R> ReactionStep for NonTransactional<R>
+where
+    R: Reaction,
+{
+    type Error = R::Error;
+
+    fn reaction_name() -> &'static str {
+        R::reaction_name()
+    }
+
+    fn predicate(&self) -> ReactionPredicate {
+        self.0.predicate()
+    }
+
+    fn interval() -> Duration {
+        R::interval()
+    }
+
+    fn react_and_save<'conn, C>(
+        &mut self,
+        conn: &C,
+        events: Vec<RawEvent>,
+        checkpoint: Option<&str>,
+        lease_owner: Option<&str>,
+    ) -> Result<Result<(), Self::Error>, StepError<C::Error>>
+    where
+        C: DbConnection<'conn>,
+    {
+        for event in events {
+            let event_id = event.event_id;
+            if let Err(err) = self.0.react(event) {
+                return Ok(Err(err));
+            }
+
+            if let Some(name) = checkpoint {
+                if !conn.save_since(name, event_id, lease_owner)? {
+                    return Err(StepError::LeaseLost);
+                }
+            }
+        }
+
+        Ok(Ok(()))
+    }
+}
+
+pub(crate) struct Transactional<R>(pub(crate) R);
+
+impl<R> ReactionStep for Transactional<R>
+where
+    R: TransactionalReaction,
+{
+    type Error = R::Error;
+
+    fn reaction_name() -> &'static str {
+        R::reaction_name()
+    }
+
+    fn predicate(&self) -> ReactionPredicate {
+        self.0.predicate()
+    }
+
+    fn interval() -> Duration {
+        R::interval()
+    }
+
+    fn react_and_save<'conn, C>(
+        &mut self,
+        conn: &C,
+        events: Vec<RawEvent>,
+        checkpoint: Option<&str>,
+        lease_owner: Option<&str>,
+    ) -> Result<Result<(), Self::Error>, StepError<C::Error>>
+    where
+        C: DbConnection<'conn>,
+    {
+        for event in events {
+            match conn.react_in_transaction(&mut self.0, event, checkpoint, lease_owner)? {
+                Ok(true) => {}
+                Ok(false) => return Err(StepError::LeaseLost),
+                Err(err) => return Ok(Err(err)),
+            }
+        }
+
+        Ok(Ok(()))
+    }
+}
+
+pub(crate) struct Batch<R>(pub(crate) R);
+
+impl<R> ReactionStep for Batch<R>
+where
+    R: BatchReaction,
+{
+    type Error = R::Error;
+
+    fn reaction_name() -> &'static str {
+        R::reaction_name()
+    }
+
+    fn predicate(&self) -> ReactionPredicate {
+        self.0.predicate()
+    }
+
+    fn interval() -> Duration {
+        R::interval()
+    }
+
+    fn units(events: Vec<RawEvent>) -> Vec<Vec<RawEvent>> {
+        if events.is_empty() {
+            Vec::new()
+        } else {
+            vec![events]
+        }
+    }
+
+    fn react_and_save<'conn, C>(
+        &mut self,
+        conn: &C,
+        events: Vec<RawEvent>,
+        checkpoint: Option<&str>,
+        lease_owner: Option<&str>,
+    ) -> Result<Result<(), Self::Error>, StepError<C::Error>>
+    where
+        C: DbConnection<'conn>,
+    {
+        let last_event_id = match events.last() {
+            Some(event) => event.event_id,
+            None => return Ok(Ok(())),
+        };
+
+        if let Err(err) = self.0.react_batch(events) {
+            return Ok(Err(err));
+        }
+
+        if let Some(name) = checkpoint {
+            if !conn.save_since(name, last_event_id, lease_owner)? {
+                return Err(StepError::LeaseLost);
+            }
+        }
+
+        Ok(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        db_wrapper::{sealed::Sealed, DbConnection, DbPool, ReactorError},
+        raw::{upcast, GlobalOrdering},
+        reactor::{
+            self, Batch, BatchReaction, DeadLetter, ErrorPolicy, Lease, NonTransactional,
+            Partition, PostgresReactor, ReactionHandle, ReactionPosition, ReactionStatus,
+            ReactionStep, ReactionSupervisor, SupervisorError, Transactional,
+            TransactionalReaction, WakeStrategy,
+        },
+    };
+    use cqrs_core::{
+        reactor::{
+            AggregatePredicate, EntityIdPredicate, EventTypesPredicate, Reaction,
+            ReactionPredicate, Reactor, SpecificAggregatePredicate,
+        },
+        CqrsError, EventNumber, EventUpcasters, RawEvent, Since, UpcastError,
+    };
+    use lazy_static::lazy_static;
+    use parking_lot::{Condvar, Mutex};
+    use postgres::{error, transaction::Transaction, types::ToSql, Connection};
+    use r2d2_postgres::{r2d2::Pool, PostgresConnectionManager, TlsMode};
+    use std::{
+        any::Any,
+        io::{self, Error},
+        sync::Arc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    /// How long a test waits for a reactor before failing.
+    const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    lazy_static! {
+        static ref PREDICATE: Mutex<ReactionPredicate> = Mutex::new(ReactionPredicate::default());
+        static ref RAW_EVENT: RawEvent = RawEvent {
+            event_id: EventNumber::new(1).unwrap(),
+            aggregate_type: String::from(""),
+            entity_id: String::from(""),
+            sequence: EventNumber::new(1).unwrap(),
+            event_type: String::from(""),
+            payload_format: String::from("json"),
+            payload: Vec::from("{}"),
+        };
+        static ref RAW_EVENTS: Vec<RawEvent> = vec![RAW_EVENT.clone(), RAW_EVENT.clone(),];
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct MockPool {
+        get_result: Result<MockConnection, String>,
+    }
+
+    impl Sealed for MockPool {}
+
+    impl<'conn> DbPool<'conn> for MockPool {
         type Connection = MockConnection;
         type Error = String;
 
@@ -245,16 +1280,16 @@ mod tests {
     #[derive(Debug, Clone)]
     pub struct SaveSince {
         expected_reaction_name: String,
-        expected_event_id: EventNumber,
-        result: Result<(), String>,
+        expected_event_id: Option<EventNumber>,
+        result: Result<bool, String>,
     }
 
     impl Default for SaveSince {
         fn default() -> Self {
             SaveSince {
                 expected_reaction_name: String::from("Mock"),
-                expected_event_id: EventNumber::MIN_VALUE,
-                result: Ok(()),
+                expected_event_id: Some(EventNumber::MIN_VALUE),
+                result: Ok(true),
             }
         }
     }
@@ -278,11 +1313,64 @@ mod tests {
         }
     }
 
+    #[derive(Debug, Clone)]
+    pub struct Listen {
+        expected_channel: String,
+        result: Result<(), String>,
+    }
+
+    impl Default for Listen {
+        fn default() -> Self {
+            Listen {
+                expected_channel: String::from(reactor::EVENTS_CHANNEL),
+                result: Ok(()),
+            }
+        }
+    }
+
+    /// Signalled when a reactor has reacted to all of a mock connection's events, is waiting on standby, or has stopped.
+    #[derive(Debug, Default)]
+    pub struct Idle {
+        idle: Mutex<bool>,
+        signal: Condvar,
+    }
+
+    impl Idle {
+        fn notify(&self) {
+            *self.idle.lock() = true;
+            self.signal.notify_all();
+        }
+
+        fn wait(&self) {
+            let deadline = Instant::now() + TEST_TIMEOUT;
+            let mut idle = self.idle.lock();
+            while !*idle {
+                if self.signal.wait_until(&mut idle, deadline).timed_out() {
+                    panic!("reactor did not become idle within {:?}", TEST_TIMEOUT);
+                }
+            }
+        }
+    }
+
     #[derive(Debug, Clone)]
     pub struct MockConnection {
         load_since_data: LoadSince,
         save_since_data: SaveSince,
         read_all_events_data: ReadAllEvents,
+        listen_data: Listen,
+        react_in_transaction_result: Result<(), String>,
+        transactional_react_error: Option<String>,
+        dead_letters: Vec<DeadLetter>,
+        saved_dead_letters: Arc<Mutex<Vec<(EventNumber, String)>>>,
+        deleted_dead_letters: Arc<Mutex<Vec<EventNumber>>>,
+        acquire_lease_result: Result<bool, String>,
+        released_leases: Arc<Mutex<Vec<String>>>,
+        reaction_positions: Vec<ReactionPosition>,
+        deleted_since: Arc<Mutex<Vec<String>>>,
+        saved_since: Arc<Mutex<Vec<EventNumber>>>,
+        read_count: Arc<Mutex<usize>>,
+        lease_lost: Arc<Mutex<bool>>,
+        idle: Arc<Idle>,
     }
 
     impl Default for MockConnection {
@@ -291,10 +1379,26 @@ mod tests {
                 load_since_data: LoadSince::default(),
                 save_since_data: SaveSince::default(),
                 read_all_events_data: ReadAllEvents::default(),
+                listen_data: Listen::default(),
+                react_in_transaction_result: Ok(()),
+                transactional_react_error: None,
+                dead_letters: vec![],
+                saved_dead_letters: Arc::default(),
+                deleted_dead_letters: Arc::default(),
+                acquire_lease_result: Ok(true),
+                released_leases: Arc::default(),
+                reaction_positions: vec![],
+                deleted_since: Arc::default(),
+                saved_since: Arc::default(),
+                read_count: Arc::default(),
+                lease_lost: Arc::default(),
+                idle: Arc::default(),
             }
         }
     }
 
+    impl Sealed for MockConnection {}
+
     impl<'conn> DbConnection<'conn> for MockConnection {
         type Error = String;
 
@@ -307,10 +1411,18 @@ mod tests {
             &self,
             reaction_name: &str,
             event_id: EventNumber,
-        ) -> Result<(), Self::Error> {
+            _lease_owner: Option<&str>,
+        ) -> Result<bool, Self::Error> {
             assert_eq!(reaction_name, self.save_since_data.expected_reaction_name);
-            assert_eq!(event_id, self.save_since_data.expected_event_id);
-            self.save_since_data.result.clone()
+            if let Some(expected_event_id) = self.save_since_data.expected_event_id {
+                assert_eq!(event_id, expected_event_id);
+            }
+            self.saved_since.lock().push(event_id);
+            let saved = self.save_since_data.result.clone()?;
+            if !saved {
+                *self.lease_lost.lock() = true;
+            }
+            Ok(saved)
         }
 
         fn read_all_events(
@@ -318,7 +1430,8 @@ mod tests {
             query: &str,
             since: Since,
             params: &[Box<dyn ToSql>],
-        ) -> Result<Vec<RawEvent>, Self::Error> {
+            upcasters: &EventUpcasters,
+        ) -> Result<Result<Vec<RawEvent>, UpcastError>, Self::Error> {
             assert_eq!(since, self.read_all_events_data.expected_since);
 
             if let Some(expected_query) = &self.read_all_events_data.expected_query {
@@ -329,7 +1442,153 @@ mod tests {
                 assert_eq!(&format!("{:?}", params), expected_params);
             }
 
-            self.read_all_events_data.result.clone()
+            // The events are only read once, as the reactor would only read them once from a real event store.
+            let mut read_count = self.read_count.lock();
+            *read_count += 1;
+            let events = if *read_count == 1 {
+                self.read_all_events_data.result.clone()?
+            } else {
+                vec![]
+            };
+
+            if events.is_empty() {
+                self.idle.notify();
+            }
+
+            // The mock events are all persisted with the first schema version of their event type.
+            Ok(events
+                .into_iter()
+                .map(|event| {
+                    let payload = upcast(upcasters, &event.event_type, 0, &event.payload)?;
+                    Ok(RawEvent {
+                        payload: payload.into_owned(),
+                        ..event
+                    })
+                })
+                .collect())
+        }
+
+        fn delete_since(&self, reaction_name: &str) -> Result<(), Self::Error> {
+            self.deleted_since.lock().push(String::from(reaction_name));
+            Ok(())
+        }
+
+        fn read_reaction_positions(&self) -> Result<Vec<ReactionPosition>, Self::Error> {
+            Ok(self.reaction_positions.clone())
+        }
+
+        fn react_in_transaction<R>(
+            &self,
+            _reaction: &mut R,
+            event: RawEvent,
+            checkpoint: Option<&str>,
+            lease_owner: Option<&str>,
+        ) -> Result<Result<bool, R::Error>, Self::Error>
+        where
+            R: TransactionalReaction,
+        {
+            if let Some(ref error) = self.transactional_react_error {
+                let error: Box<dyn Any> = Box::new(error.clone());
+                return Ok(Err(*error
+                    .downcast()
+                    .expect("Mock transactional reactions fail with a String")));
+            }
+
+            self.react_in_transaction_result.clone()?;
+            match checkpoint {
+                Some(reaction_name) => self
+                    .save_since(reaction_name, event.event_id, lease_owner)
+                    .map(Ok),
+                None => Ok(Ok(true)),
+            }
+        }
+
+        fn save_dead_letter(
+            &self,
+            reaction_name: &str,
+            event_id: EventNumber,
+            error: &str,
+        ) -> Result<(), Self::Error> {
+            assert_eq!(reaction_name, self.save_since_data.expected_reaction_name);
+            self.saved_dead_letters
+                .lock()
+                .push((event_id, String::from(error)));
+            Ok(())
+        }
+
+        fn delete_dead_letter(
+            &self,
+            reaction_name: &str,
+            event_id: EventNumber,
+        ) -> Result<(), Self::Error> {
+            assert_eq!(reaction_name, self.save_since_data.expected_reaction_name);
+            self.deleted_dead_letters.lock().push(event_id);
+            Ok(())
+        }
+
+        fn read_dead_letters(
+            &self,
+            reaction_name: &str,
+            requeued_only: bool,
+            _upcasters: &EventUpcasters,
+        ) -> Result<Result<Vec<DeadLetter>, UpcastError>, Self::Error> {
+            assert_eq!(reaction_name, self.save_since_data.expected_reaction_name);
+            let deleted_dead_letters = self.deleted_dead_letters.lock();
+            Ok(Ok(self
+                .dead_letters
+                .iter()
+                .filter(|d| d.requeued || !requeued_only)
+                .filter(|d| !deleted_dead_letters.contains(&d.event.event_id))
+                .cloned()
+                .collect()))
+        }
+
+        fn requeue_dead_letters(
+            &self,
+            reaction_name: &str,
+            event_ids: &[EventNumber],
+        ) -> Result<u64, Self::Error> {
+            assert_eq!(reaction_name, self.save_since_data.expected_reaction_name);
+            Ok(self
+                .dead_letters
+                .iter()
+                .filter(|d| event_ids.contains(&d.event.event_id))
+                .count() as u64)
+        }
+
+        fn listen(&self, channel: &str) -> Result<(), Self::Error> {
+            assert_eq!(channel, self.listen_data.expected_channel);
+            self.listen_data.result.clone()
+        }
+
+        fn unlisten(&self, channel: &str) -> Result<(), Self::Error> {
+            assert_eq!(channel, self.listen_data.expected_channel);
+            Ok(())
+        }
+
+        fn wait_for_notification(&self, timeout: Duration) -> Result<bool, Self::Error> {
+            thread::sleep(timeout);
+            Ok(false)
+        }
+
+        fn acquire_lease(
+            &self,
+            reaction_name: &str,
+            _owner: &str,
+            _duration: Duration,
+        ) -> Result<bool, Self::Error> {
+            assert_eq!(reaction_name, self.save_since_data.expected_reaction_name);
+            let acquired = !*self.lease_lost.lock() && self.acquire_lease_result.clone()?;
+            if !acquired {
+                self.idle.notify();
+            }
+            Ok(acquired)
+        }
+
+        fn release_lease(&self, reaction_name: &str, owner: &str) -> Result<(), Self::Error> {
+            assert_eq!(reaction_name, self.save_since_data.expected_reaction_name);
+            self.released_leases.lock().push(String::from(owner));
+            Ok(())
         }
     }
 
@@ -369,7 +1628,7 @@ mod tests {
         }
 
         fn predicate(&self) -> ReactionPredicate {
-            self.predicate
+            self.predicate.clone()
         }
 
         fn interval() -> Duration {
@@ -377,47 +1636,157 @@ mod tests {
         }
     }
 
-    #[test]
-    fn can_read_all_aggregates_and_all_events() {
-        let pool = ok_pool(
-            String::from(
-                "SELECT event_id, aggregate_type, entity_id, sequence, event_type, payload \
-                 FROM events \
-                 WHERE event_id > $1 \
-                 ORDER BY event_id ASC \
-                 LIMIT $2",
-            ),
-            String::from("[100]"),
-        );
+    /// A reaction which waits an hour between batches once it has caught up.
+    #[derive(Clone, Debug, Default)]
+    pub struct PatientReaction;
 
-        let reaction = MockReaction::default();
+    impl Reaction for PatientReaction {
+        type Error = String;
 
-        assert_eq!(2, test_reaction(pool, reaction).unwrap());
-    }
+        fn reaction_name() -> &'static str {
+            "Mock"
+        }
 
-    #[test]
-    fn can_read_specific_aggregates_and_all_events() {
-        let pool = ok_pool(
-            String::from(
-                "SELECT event_id, aggregate_type, entity_id, sequence, event_type, payload \
-                 FROM events \
-                 WHERE event_id > $1 \
-                 AND (FALSE OR (aggregate_type = $2)) \
-                 ORDER BY event_id ASC \
-                 LIMIT $3",
-            ),
-            String::from("[\"material_location_availability\", 100]"),
-        );
+        fn react(&mut self, _event: RawEvent) -> Result<(), Self::Error> {
+            Ok(())
+        }
 
-        let reaction = MockReaction {
-            predicate: ReactionPredicate {
-                aggregate_predicate: AggregatePredicate::SpecificAggregates(&[
-                    SpecificAggregatePredicate {
-                        aggregate_type: "material_location_availability",
-                        event_types: EventTypesPredicate::AllEventTypes,
-                    },
-                ]),
-            },
+        fn predicate(&self) -> ReactionPredicate {
+            ReactionPredicate::default()
+        }
+
+        fn interval() -> Duration {
+            Duration::from_secs(3600)
+        }
+    }
+
+    #[derive(Clone, Debug, Default)]
+    pub struct MockTransactionalReaction {
+        predicate: ReactionPredicate,
+    }
+
+    impl TransactionalReaction for MockTransactionalReaction {
+        type Error = String;
+
+        fn reaction_name() -> &'static str {
+            "Mock"
+        }
+
+        fn react(
+            &mut self,
+            _event: RawEvent,
+            _transaction: &Transaction,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn predicate(&self) -> ReactionPredicate {
+            self.predicate.clone()
+        }
+
+        fn interval() -> Duration {
+            Duration::from_millis(100)
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct MockBatchReaction {
+        expected_batch_len: Option<usize>,
+        expected_payload: Option<Vec<u8>>,
+        failing_event_id: Option<EventNumber>,
+        react_result: Result<(), String>,
+    }
+
+    impl Default for MockBatchReaction {
+        fn default() -> Self {
+            MockBatchReaction {
+                expected_batch_len: Some(RAW_EVENTS.len()),
+                expected_payload: None,
+                failing_event_id: None,
+                react_result: Ok(()),
+            }
+        }
+    }
+
+    impl BatchReaction for MockBatchReaction {
+        type Error = String;
+
+        fn reaction_name() -> &'static str {
+            "Mock"
+        }
+
+        fn react_batch(&mut self, events: Vec<RawEvent>) -> Result<(), Self::Error> {
+            if let Some(expected_batch_len) = self.expected_batch_len {
+                assert_eq!(events.len(), expected_batch_len);
+            }
+            if let Some(ref expected_payload) = self.expected_payload {
+                for event in &events {
+                    assert_eq!(&event.payload, expected_payload);
+                }
+            }
+            if let Some(failing_event_id) = self.failing_event_id {
+                if events
+                    .iter()
+                    .any(|event| event.event_id == failing_event_id)
+                {
+                    return Err(String::from("react batch error"));
+                }
+            }
+            self.react_result.clone()
+        }
+
+        fn predicate(&self) -> ReactionPredicate {
+            ReactionPredicate::default()
+        }
+
+        fn interval() -> Duration {
+            Duration::from_millis(100)
+        }
+    }
+
+    #[test]
+    fn can_read_all_aggregates_and_all_events() {
+        let pool = ok_pool(
+            String::from(
+                "SELECT event_id, aggregate_type, entity_id, sequence, event_type, \
+                 COALESCE(binary_payload, convert_to(payload::text, 'UTF8')), schema_version, \
+                 payload_format \
+                 FROM events \
+                 WHERE event_id > $1 \
+                 ORDER BY event_id ASC \
+                 LIMIT $2",
+            ),
+            String::from("[100]"),
+        );
+
+        let reaction = MockReaction::default();
+
+        assert_eq!(2, test_reaction(pool, reaction).unwrap());
+    }
+
+    #[test]
+    fn can_read_specific_aggregates_and_all_events() {
+        let pool = ok_pool(
+            String::from(
+                "SELECT event_id, aggregate_type, entity_id, sequence, event_type, \
+                 COALESCE(binary_payload, convert_to(payload::text, 'UTF8')), schema_version, \
+                 payload_format \
+                 FROM events \
+                 WHERE event_id > $1 \
+                 AND (FALSE OR (aggregate_type = $2)) \
+                 ORDER BY event_id ASC \
+                 LIMIT $3",
+            ),
+            String::from("[\"material_location_availability\", 100]"),
+        );
+
+        let reaction = MockReaction {
+            predicate: ReactionPredicate::new(AggregatePredicate::SpecificAggregates(vec![
+                SpecificAggregatePredicate::new(
+                    "material_location_availability",
+                    EventTypesPredicate::AllEventTypes,
+                ),
+            ])),
             ..MockReaction::default()
         };
 
@@ -428,7 +1797,9 @@ mod tests {
     fn can_read_all_aggregates_and_specific_events() {
         let pool = ok_pool(
             String::from(
-                "SELECT event_id, aggregate_type, entity_id, sequence, event_type, payload \
+                "SELECT event_id, aggregate_type, entity_id, sequence, event_type, \
+                 COALESCE(binary_payload, convert_to(payload::text, 'UTF8')), schema_version, \
+                 payload_format \
                  FROM events \
                  WHERE event_id > $1 \
                  AND event_type = ANY ($2) \
@@ -439,11 +1810,9 @@ mod tests {
         );
 
         let reaction = MockReaction {
-            predicate: ReactionPredicate {
-                aggregate_predicate: AggregatePredicate::AllAggregates(
-                    EventTypesPredicate::SpecificEventTypes(&["sources_updated"]),
-                ),
-            },
+            predicate: ReactionPredicate::new(AggregatePredicate::AllAggregates(
+                EventTypesPredicate::specific(&["sources_updated"]),
+            )),
             ..MockReaction::default()
         };
 
@@ -454,7 +1823,9 @@ mod tests {
     fn can_read_specific_aggregates_and_specific_events() {
         let pool = ok_pool(
             String::from(
-                "SELECT event_id, aggregate_type, entity_id, sequence, event_type, payload \
+                "SELECT event_id, aggregate_type, entity_id, sequence, event_type, \
+                 COALESCE(binary_payload, convert_to(payload::text, 'UTF8')), schema_version, \
+                 payload_format \
                  FROM events \
                  WHERE event_id > $1 \
                  AND (FALSE OR (aggregate_type = $2 AND event_type = ANY ($3))) \
@@ -465,23 +1836,147 @@ mod tests {
         );
 
         let reaction = MockReaction {
-            predicate: ReactionPredicate {
-                aggregate_predicate: AggregatePredicate::SpecificAggregates(&[
-                    SpecificAggregatePredicate {
-                        aggregate_type: "material_location_availability",
-                        event_types: EventTypesPredicate::SpecificEventTypes(&[
-                            "sources_updated",
-                            "end_of_life_updated",
-                        ]),
-                    },
-                ]),
-            },
+            predicate: ReactionPredicate::new(AggregatePredicate::SpecificAggregates(vec![
+                SpecificAggregatePredicate::new(
+                    "material_location_availability",
+                    EventTypesPredicate::specific(&["sources_updated", "end_of_life_updated"]),
+                ),
+            ])),
+            ..MockReaction::default()
+        };
+
+        assert_eq!(2, test_reaction(pool, reaction).unwrap());
+    }
+
+    #[test]
+    fn can_read_specific_entities_with_metadata() {
+        let pool = ok_pool(
+            String::from(
+                "SELECT event_id, aggregate_type, entity_id, sequence, event_type, \
+                 COALESCE(binary_payload, convert_to(payload::text, 'UTF8')), schema_version, \
+                 payload_format \
+                 FROM events \
+                 WHERE event_id > $1 \
+                 AND entity_id LIKE $2 \
+                 AND metadata->>$3 = $4 \
+                 ORDER BY event_id ASC \
+                 LIMIT $5",
+            ),
+            String::from("[\"order_%\", \"initiated_by\", \"admin\", 100]"),
+        );
+
+        let reaction = MockReaction {
+            predicate: ReactionPredicate::default()
+                .with_entity_ids(EntityIdPredicate::MatchingPattern(String::from("order_%")))
+                .with_metadata("initiated_by", "admin"),
             ..MockReaction::default()
         };
 
         assert_eq!(2, test_reaction(pool, reaction).unwrap());
     }
 
+    #[test]
+    fn can_read_a_partition() {
+        let connection = MockConnection {
+            load_since_data: LoadSince {
+                expected_reaction_name: String::from("Mock[1/4]"),
+                ..LoadSince::default()
+            },
+            save_since_data: SaveSince {
+                expected_reaction_name: String::from("Mock[1/4]"),
+                ..SaveSince::default()
+            },
+            read_all_events_data: ReadAllEvents {
+                expected_query: Some(String::from(
+                    "SELECT event_id, aggregate_type, entity_id, sequence, event_type, \
+                     COALESCE(binary_payload, convert_to(payload::text, 'UTF8')), schema_version, \
+                     payload_format \
+                     FROM events \
+                     WHERE event_id > $1 \
+                     AND event_type = ANY ($2) \
+                     AND mod(('x' || substr(md5(entity_id), 1, 15))::bit(60)::bigint, $3) = $4 \
+                     ORDER BY event_id ASC \
+                     LIMIT $5",
+                )),
+                expected_params: Some(String::from("[[\"sources_updated\"], 4, 1, 100]")),
+                result: Ok(RAW_EVENTS.to_vec()),
+                ..ReadAllEvents::default()
+            },
+            ..MockConnection::default()
+        };
+        let pool = MockPool {
+            get_result: Ok(connection),
+        };
+        let reactor = PostgresReactor::new(pool).with_partition(Partition::new(1, 4));
+
+        let reaction = MockReaction {
+            predicate: ReactionPredicate::new(AggregatePredicate::AllAggregates(
+                EventTypesPredicate::specific(&["sources_updated"]),
+            )),
+            ..MockReaction::default()
+        };
+
+        assert_eq!(
+            2,
+            run_until_idle(reactor, NonTransactional(reaction)).unwrap()
+        );
+    }
+
+    #[test]
+    fn can_read_in_gap_safe_order() {
+        let connection = MockConnection {
+            read_all_events_data: ReadAllEvents {
+                expected_query: Some(String::from(
+                    "SELECT event_id, aggregate_type, entity_id, sequence, event_type, \
+                     COALESCE(binary_payload, convert_to(payload::text, 'UTF8')), schema_version, \
+                     payload_format \
+                     FROM events \
+                     WHERE (transaction_id, event_id) > \
+                     (COALESCE((SELECT e.transaction_id FROM events e WHERE e.event_id = $1), 0), $1) \
+                     AND transaction_id < txid_snapshot_xmin(txid_current_snapshot()) \
+                     AND event_type = ANY ($2) \
+                     ORDER BY transaction_id ASC, event_id ASC \
+                     LIMIT $3",
+                )),
+                expected_params: Some(String::from("[[\"sources_updated\"], 100]")),
+                result: Ok(RAW_EVENTS.to_vec()),
+                ..ReadAllEvents::default()
+            },
+            ..MockConnection::default()
+        };
+        let pool = MockPool {
+            get_result: Ok(connection),
+        };
+        let reactor = PostgresReactor::new(pool).with_global_ordering(GlobalOrdering::GapSafe);
+
+        let reaction = MockReaction {
+            predicate: ReactionPredicate::new(AggregatePredicate::AllAggregates(
+                EventTypesPredicate::specific(&["sources_updated"]),
+            )),
+            ..MockReaction::default()
+        };
+
+        assert_eq!(
+            2,
+            run_until_idle(reactor, NonTransactional(reaction)).unwrap()
+        );
+    }
+
+    #[test]
+    fn all_partitions_are_distinct() {
+        let names: Vec<_> = Partition::all(3)
+            .map(|partition| partition.checkpoint_name("Mock"))
+            .collect();
+
+        assert_eq!(vec!["Mock[0/3]", "Mock[1/3]", "Mock[2/3]"], names);
+    }
+
+    #[test]
+    #[should_panic]
+    fn partition_index_must_be_less_than_count() {
+        Partition::new(4, 4);
+    }
+
     #[test]
     fn get_connection_error() {
         let error_message = "connection pool error";
@@ -629,34 +2124,1025 @@ mod tests {
         );
     }
 
-    fn ok_pool(expected_query: String, expected_params: String) -> MockPool {
+    #[test]
+    fn can_wake_on_notification() {
+        let pool = ok_pool(
+            String::from(
+                "SELECT event_id, aggregate_type, entity_id, sequence, event_type, \
+                 COALESCE(binary_payload, convert_to(payload::text, 'UTF8')), schema_version, \
+                 payload_format \
+                 FROM events \
+                 WHERE event_id > $1 \
+                 ORDER BY event_id ASC \
+                 LIMIT $2",
+            ),
+            String::from("[100]"),
+        );
+
+        let reaction = MockReaction::default();
+
+        assert_eq!(
+            2,
+            test_reaction_with_wake_strategy(pool, reaction, WakeStrategy::Notify).unwrap()
+        );
+    }
+
+    #[test]
+    fn listen_error() {
+        let error_message = "listen error";
+        let test_error = Err(String::from(error_message));
+
+        let connection = MockConnection {
+            listen_data: Listen {
+                result: test_error,
+                ..Listen::default()
+            },
+            ..MockConnection::default()
+        };
+
+        let pool = MockPool {
+            get_result: Ok(connection),
+        };
+
+        let reaction = MockReaction {
+            ..MockReaction::default()
+        };
+
+        let result = test_reaction_with_wake_strategy(pool, reaction, WakeStrategy::Notify);
+
+        assert!(result.is_err());
+        assert_eq!(
+            "Postgres error during reaction: listen error",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn notify_trigger_uses_events_channel() {
+        let migration = crate::migrations::MIGRATIONS
+            .iter()
+            .find(|m| m.name == "create_events_notify_trigger")
+            .unwrap();
+
+        assert!(migration
+            .sql
+            .contains(&format!("pg_notify('{}'", reactor::EVENTS_CHANNEL)));
+    }
+
+    #[test]
+    fn can_react_in_transaction() {
+        let pool = ok_pool(
+            String::from(
+                "SELECT event_id, aggregate_type, entity_id, sequence, event_type, \
+                 COALESCE(binary_payload, convert_to(payload::text, 'UTF8')), schema_version, \
+                 payload_format \
+                 FROM events \
+                 WHERE event_id > $1 \
+                 ORDER BY event_id ASC \
+                 LIMIT $2",
+            ),
+            String::from("[100]"),
+        );
+
+        let reaction = MockTransactionalReaction::default();
+
+        assert_eq!(
+            2,
+            run_until_idle(PostgresReactor::new(pool), Transactional(reaction)).unwrap()
+        );
+    }
+
+    #[test]
+    fn react_in_transaction_error() {
+        let error_message = "transaction error";
+        let test_error = Err(String::from(error_message));
+
         let connection = MockConnection {
             read_all_events_data: ReadAllEvents {
-                expected_query: Some(expected_query),
-                expected_params: Some(expected_params),
                 result: Ok(RAW_EVENTS.to_vec()),
                 ..ReadAllEvents::default()
             },
+            react_in_transaction_result: test_error,
             ..MockConnection::default()
         };
 
-        MockPool {
+        let pool = MockPool {
             get_result: Ok(connection),
-        }
-    }
+        };
 
-    fn test_reaction(
-        pool: MockPool,
-        reaction: MockReaction,
-    ) -> Result<usize, ReactorError<MockReaction, impl CqrsError, impl CqrsError>> {
-        let local_reactor = Arc::new(PostgresReactor::new(pool));
-        let thread_reactor = Arc::clone(&local_reactor);
+        let result = run_until_idle(
+            PostgresReactor::new(pool),
+            Transactional(MockTransactionalReaction::default()),
+        );
 
-        let handle = thread::spawn(move || thread_reactor.start_reaction(reaction));
+        assert!(result.is_err());
+        assert_eq!(
+            "Postgres error during reaction: transaction error",
+            result.err().unwrap().to_string()
+        );
+    }
 
-        ::std::thread::sleep(Duration::from_millis(50));
-        local_reactor.stop_reaction();
+    #[test]
+    fn transactional_reaction_error_leaves_position_unsaved() {
+        let connection = MockConnection {
+            read_all_events_data: ReadAllEvents {
+                result: Ok(RAW_EVENTS.to_vec()),
+                ..ReadAllEvents::default()
+            },
+            transactional_react_error: Some(String::from("react error")),
+            ..MockConnection::default()
+        };
+
+        let pool = MockPool {
+            get_result: Ok(connection.clone()),
+        };
+
+        let result = run_until_idle(
+            PostgresReactor::new(pool),
+            Transactional(MockTransactionalReaction::default()),
+        );
+
+        assert_eq!(
+            "React error during reaction: react error",
+            result.err().unwrap().to_string()
+        );
+        assert!(connection.saved_since.lock().is_empty());
+    }
+
+    #[test]
+    fn can_react_in_batches_of_configured_size() {
+        let pool = ok_pool(
+            String::from(
+                "SELECT event_id, aggregate_type, entity_id, sequence, event_type, \
+                 COALESCE(binary_payload, convert_to(payload::text, 'UTF8')), schema_version, \
+                 payload_format \
+                 FROM events \
+                 WHERE event_id > $1 \
+                 ORDER BY event_id ASC \
+                 LIMIT $2",
+            ),
+            String::from("[10]"),
+        );
+
+        let reactor = PostgresReactor::new(pool).with_batch_size(10);
+
+        assert_eq!(
+            2,
+            run_until_idle(reactor, Batch(MockBatchReaction::default())).unwrap()
+        );
+    }
+
+    #[test]
+    fn react_batch_error() {
+        let error_message = "react batch error";
+        let test_error = Err(String::from(error_message));
+
+        let connection = MockConnection {
+            read_all_events_data: ReadAllEvents {
+                result: Ok(RAW_EVENTS.to_vec()),
+                ..ReadAllEvents::default()
+            },
+            save_since_data: SaveSince {
+                result: Err(String::from("checkpoint saved after failed batch")),
+                ..SaveSince::default()
+            },
+            ..MockConnection::default()
+        };
+
+        let pool = MockPool {
+            get_result: Ok(connection),
+        };
+
+        let reaction = MockBatchReaction {
+            react_result: test_error,
+            ..MockBatchReaction::default()
+        };
+
+        let result = run_until_idle(PostgresReactor::new(pool), Batch(reaction));
+
+        assert!(result.is_err());
+        assert_eq!(
+            "React error during reaction: react batch error",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn upcasts_events_before_reacting() {
+        let connection = MockConnection {
+            read_all_events_data: ReadAllEvents {
+                result: Ok(RAW_EVENTS.to_vec()),
+                ..ReadAllEvents::default()
+            },
+            ..MockConnection::default()
+        };
+
+        let pool = MockPool {
+            get_result: Ok(connection),
+        };
+
+        let upcasters = EventUpcasters::new().with_upcaster("", 0, |payload: &[u8]| {
+            assert_eq!(payload, b"{}");
+            Ok::<_, String>(Vec::from(r#"{"upcast":true}"#))
+        });
+        let reaction = MockBatchReaction {
+            expected_payload: Some(Vec::from(r#"{"upcast":true}"#)),
+            ..MockBatchReaction::default()
+        };
+
+        let reactor = PostgresReactor::new(pool).with_upcasters(upcasters);
+
+        assert_eq!(2, run_until_idle(reactor, Batch(reaction)).unwrap());
+    }
+
+    #[test]
+    fn upcast_error_stops_reaction() {
+        let connection = MockConnection {
+            read_all_events_data: ReadAllEvents {
+                result: Ok(RAW_EVENTS.to_vec()),
+                ..ReadAllEvents::default()
+            },
+            ..MockConnection::default()
+        };
+
+        let pool = MockPool {
+            get_result: Ok(connection),
+        };
+
+        let upcasters = EventUpcasters::new()
+            .with_upcaster("", 0, |_: &[u8]| Err::<Vec<u8>, _>("invalid payload"));
+
+        let result = run_until_idle(
+            PostgresReactor::new(pool).with_upcasters(upcasters),
+            NonTransactional(MockReaction::default()),
+        );
+
+        assert_eq!(
+            "Upcast error during reaction: unable to upcast event type  from schema version 0: invalid payload",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn supervisor_runs_registered_reaction_until_stopped() {
+        let pool = ok_pool(
+            String::from(
+                "SELECT event_id, aggregate_type, entity_id, sequence, event_type, \
+                 COALESCE(binary_payload, convert_to(payload::text, 'UTF8')), schema_version, \
+                 payload_format \
+                 FROM events \
+                 WHERE event_id > $1 \
+                 ORDER BY event_id ASC \
+                 LIMIT $2",
+            ),
+            String::from("[100]"),
+        );
+
+        let idle = Arc::clone(&pool.get_result.as_ref().unwrap().idle);
+
+        let supervisor = ReactionSupervisor::new(pool);
+        supervisor.register(MockReaction::default).unwrap();
+        assert_eq!(Some(ReactionStatus::Idle), supervisor.status("Mock"));
+
+        supervisor.start("Mock").unwrap();
+        assert_eq!(Some(ReactionStatus::Running), supervisor.status("Mock"));
+        assert_eq!(
+            Err(SupervisorError::AlreadyRunning("Mock")),
+            supervisor.start("Mock")
+        );
+
+        idle.wait();
+        supervisor.stop("Mock").unwrap();
+        assert_eq!(
+            vec![("Mock", ReactionStatus::Stopped { event_count: 2 })],
+            supervisor.statuses()
+        );
+    }
+
+    #[test]
+    fn supervisor_reports_failed_reaction() {
+        let pool = MockPool {
+            get_result: Err(String::from("connection pool error")),
+        };
+
+        let supervisor = ReactionSupervisor::new(pool);
+        supervisor.register(MockReaction::default).unwrap();
+        supervisor.start_all();
+
+        wait_until(|| supervisor.status("Mock") != Some(ReactionStatus::Running));
+        assert_eq!(
+            Some(ReactionStatus::Failed {
+                error: String::from("Pool error during reaction: connection pool error")
+            }),
+            supervisor.status("Mock")
+        );
+    }
+
+    #[test]
+    fn supervisor_reports_panicked_reaction_and_can_restart_it() {
+        struct PanickingReaction;
+
+        impl Reaction for PanickingReaction {
+            type Error = String;
+
+            fn reaction_name() -> &'static str {
+                "Mock"
+            }
+
+            fn react(&mut self, _event: RawEvent) -> Result<(), Self::Error> {
+                panic!("reaction failed")
+            }
+
+            fn predicate(&self) -> ReactionPredicate {
+                ReactionPredicate::default()
+            }
+
+            fn interval() -> Duration {
+                Duration::from_millis(10)
+            }
+        }
+
+        let supervisor = ReactionSupervisor::new(MockPool {
+            get_result: Ok(MockConnection {
+                read_all_events_data: ReadAllEvents {
+                    result: Ok(RAW_EVENTS.to_vec()),
+                    ..ReadAllEvents::default()
+                },
+                ..MockConnection::default()
+            }),
+        });
+        supervisor.register(|| PanickingReaction).unwrap();
+
+        supervisor.start("Mock").unwrap();
+
+        wait_until(|| supervisor.status("Mock") != Some(ReactionStatus::Running));
+        assert_eq!(
+            Some(ReactionStatus::Failed {
+                error: String::from("reaction panicked")
+            }),
+            supervisor.status("Mock")
+        );
+
+        supervisor.start("Mock").unwrap();
+        supervisor.stop("Mock").unwrap();
+        assert_eq!(
+            Some(ReactionStatus::Stopped { event_count: 0 }),
+            supervisor.status("Mock")
+        );
+    }
+
+    #[test]
+    fn supervisor_rejects_unknown_and_duplicate_reactions() {
+        let supervisor = ReactionSupervisor::new(MockPool {
+            get_result: Ok(MockConnection::default()),
+        });
+
+        supervisor.register(MockReaction::default).unwrap();
+        assert_eq!(
+            Err(SupervisorError::AlreadyRegistered("Mock")),
+            supervisor.register(MockReaction::default)
+        );
+        assert_eq!(
+            Err(SupervisorError::NotRegistered(String::from("Null"))),
+            supervisor.start("Null")
+        );
+        assert_eq!(None, supervisor.status("Null"));
+    }
+
+    #[test]
+    fn supervisor_runs_every_kind_of_reaction() {
+        fn pool(reaction_name: &str) -> MockPool {
+            MockPool {
+                get_result: Ok(MockConnection {
+                    load_since_data: LoadSince {
+                        expected_reaction_name: String::from(reaction_name),
+                        ..LoadSince::default()
+                    },
+                    save_since_data: SaveSince {
+                        expected_reaction_name: String::from(reaction_name),
+                        ..SaveSince::default()
+                    },
+                    read_all_events_data: ReadAllEvents {
+                        result: Ok(RAW_EVENTS.to_vec()),
+                        ..ReadAllEvents::default()
+                    },
+                    ..MockConnection::default()
+                }),
+            }
+        }
+
+        fn assert_reacts(supervisor: &ReactionSupervisor<MockPool>, pool: &MockPool) {
+            supervisor.start("Mock").unwrap();
+            pool.get_result.as_ref().unwrap().idle.wait();
+            supervisor.stop("Mock").unwrap();
+            assert_eq!(
+                Some(ReactionStatus::Stopped { event_count: 2 }),
+                supervisor.status("Mock")
+            );
+        }
+
+        let transactional_pool = pool("Mock");
+        let supervisor = ReactionSupervisor::new(transactional_pool.clone());
+        supervisor
+            .register_transactional(MockTransactionalReaction::default)
+            .unwrap();
+        assert_reacts(&supervisor, &transactional_pool);
+
+        let batch_pool = pool("Mock");
+        let supervisor = ReactionSupervisor::new(batch_pool.clone());
+        supervisor
+            .register_batch(MockBatchReaction::default)
+            .unwrap();
+        assert_reacts(&supervisor, &batch_pool);
+
+        let partition_pool = pool("Mock[1/4]");
+        let supervisor =
+            ReactionSupervisor::new(partition_pool.clone()).with_partition(Partition::new(1, 4));
+        supervisor.register(MockReaction::default).unwrap();
+        assert_reacts(&supervisor, &partition_pool);
+    }
+
+    fn failing_reaction_pool() -> (MockPool, MockConnection) {
+        let connection = MockConnection {
+            read_all_events_data: ReadAllEvents {
+                result: Ok(RAW_EVENTS.to_vec()),
+                ..ReadAllEvents::default()
+            },
+            ..MockConnection::default()
+        };
+
+        let pool = MockPool {
+            get_result: Ok(connection.clone()),
+        };
+
+        (pool, connection)
+    }
+
+    fn failing_reaction() -> MockReaction {
+        MockReaction {
+            react_result: Err(String::from("react error")),
+            ..MockReaction::default()
+        }
+    }
+
+    #[test]
+    fn retry_policy_stops_after_max_retries() {
+        let (pool, _) = failing_reaction_pool();
+        let reactor = PostgresReactor::new(pool).with_error_policy(ErrorPolicy::Retry {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+        });
+
+        let result = run_until_idle(reactor, NonTransactional(failing_reaction()));
+
+        assert_eq!(
+            "React error during reaction: react error",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn retry_policy_stops_retrying_once_stopped() {
+        struct CountingFailingReaction(Arc<Mutex<usize>>);
+
+        impl Reaction for CountingFailingReaction {
+            type Error = String;
+
+            fn reaction_name() -> &'static str {
+                "Mock"
+            }
+
+            fn react(&mut self, _event: RawEvent) -> Result<(), Self::Error> {
+                *self.0.lock() += 1;
+                Err(String::from("react error"))
+            }
+
+            fn predicate(&self) -> ReactionPredicate {
+                ReactionPredicate::default()
+            }
+
+            fn interval() -> Duration {
+                Duration::from_secs(1)
+            }
+        }
+
+        let (pool, _) = failing_reaction_pool();
+        let attempts = Arc::new(Mutex::new(0));
+        let handle = PostgresReactor::new(pool)
+            .with_error_policy(ErrorPolicy::Retry {
+                max_retries: 1000,
+                initial_backoff: Duration::from_secs(3600),
+                max_backoff: Duration::from_secs(3600),
+            })
+            .spawn_reaction(CountingFailingReaction(Arc::clone(&attempts)));
+
+        wait_until(|| *attempts.lock() > 0);
+
+        assert_eq!(
+            "React error during reaction: react error",
+            handle.stop_and_join().unwrap().err().unwrap().to_string()
+        );
+        assert_eq!(1, *attempts.lock());
+    }
+
+    #[test]
+    fn skip_policy_moves_past_failed_events() {
+        let (pool, _) = failing_reaction_pool();
+        let reactor = PostgresReactor::new(pool).with_error_policy(ErrorPolicy::Skip);
+
+        assert_eq!(
+            0,
+            run_until_idle(reactor, NonTransactional(failing_reaction())).unwrap()
+        );
+    }
+
+    #[test]
+    fn dead_letter_policy_records_failed_events() {
+        let (pool, connection) = failing_reaction_pool();
+        let reactor = PostgresReactor::new(pool).with_error_policy(ErrorPolicy::DeadLetter);
+
+        assert_eq!(
+            0,
+            run_until_idle(reactor, NonTransactional(failing_reaction())).unwrap()
+        );
+        assert_eq!(
+            vec![
+                (EventNumber::MIN_VALUE, String::from("react error")),
+                (EventNumber::MIN_VALUE, String::from("react error")),
+            ],
+            *connection.saved_dead_letters.lock()
+        );
+    }
+
+    #[test]
+    fn requeued_dead_letters_are_reacted_to_and_removed() {
+        let dead_letter = DeadLetter {
+            reaction_name: String::from("Mock"),
+            event: RAW_EVENT.clone(),
+            error: String::from("react error"),
+            requeued: true,
+        };
+        let connection = MockConnection {
+            dead_letters: vec![dead_letter.clone()],
+            ..MockConnection::default()
+        };
+        let pool = MockPool {
+            get_result: Ok(connection.clone()),
+        };
+        let reactor = PostgresReactor::new(pool).with_error_policy(ErrorPolicy::DeadLetter);
+
+        assert_eq!(vec![dead_letter], reactor.dead_letters("Mock").unwrap());
+        assert_eq!(
+            1,
+            reactor
+                .requeue_dead_letters("Mock", &[EventNumber::MIN_VALUE])
+                .unwrap()
+        );
+        assert_eq!(
+            1,
+            run_until_idle(reactor, NonTransactional(MockReaction::default())).unwrap()
+        );
+        assert_eq!(
+            vec![EventNumber::MIN_VALUE],
+            *connection.deleted_dead_letters.lock()
+        );
+    }
+
+    #[test]
+    fn retry_backoff_doubles_up_to_max_backoff() {
+        let policy = ErrorPolicy::Retry {
+            max_retries: 40,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        };
+
+        assert_eq!(Duration::from_secs(1), policy.backoff(0));
+        assert_eq!(Duration::from_secs(32), policy.backoff(5));
+        assert_eq!(Duration::from_secs(60), policy.backoff(6));
+        assert_eq!(Duration::from_secs(60), policy.backoff(39));
+    }
+
+    #[test]
+    fn dead_letter_policy_reacts_to_a_failed_batch_one_event_at_a_time() {
+        let second_event = RawEvent {
+            event_id: EventNumber::new(2).unwrap(),
+            ..RAW_EVENT.clone()
+        };
+        let connection = MockConnection {
+            read_all_events_data: ReadAllEvents {
+                result: Ok(vec![RAW_EVENT.clone(), second_event.clone()]),
+                ..ReadAllEvents::default()
+            },
+            save_since_data: SaveSince {
+                expected_event_id: None,
+                ..SaveSince::default()
+            },
+            ..MockConnection::default()
+        };
+        let pool = MockPool {
+            get_result: Ok(connection.clone()),
+        };
+        let reactor = PostgresReactor::new(pool).with_error_policy(ErrorPolicy::DeadLetter);
+        let reaction = MockBatchReaction {
+            expected_batch_len: None,
+            failing_event_id: Some(second_event.event_id),
+            ..MockBatchReaction::default()
+        };
+
+        assert_eq!(1, run_until_idle(reactor, Batch(reaction)).unwrap());
+        assert_eq!(
+            vec![(second_event.event_id, String::from("react batch error"))],
+            *connection.saved_dead_letters.lock()
+        );
+        assert_eq!(
+            vec![RAW_EVENT.event_id, second_event.event_id],
+            *connection.saved_since.lock()
+        );
+    }
+
+    fn lease() -> Lease {
+        Lease {
+            owner: String::from("node-1"),
+            duration: Duration::from_secs(30),
+        }
+    }
+
+    #[test]
+    fn reacts_while_holding_lease_and_releases_it_when_stopped() {
+        let connection = MockConnection {
+            read_all_events_data: ReadAllEvents {
+                result: Ok(RAW_EVENTS.to_vec()),
+                ..ReadAllEvents::default()
+            },
+            ..MockConnection::default()
+        };
+        let pool = MockPool {
+            get_result: Ok(connection.clone()),
+        };
+        let reactor = PostgresReactor::new(pool).with_lease(lease());
+
+        assert_eq!(
+            2,
+            run_until_idle(reactor, NonTransactional(MockReaction::default())).unwrap()
+        );
+        assert_eq!(
+            vec![String::from("node-1")],
+            *connection.released_leases.lock()
+        );
+    }
+
+    #[test]
+    fn waits_on_standby_while_lease_held_by_another_owner() {
+        let connection = MockConnection {
+            read_all_events_data: ReadAllEvents {
+                result: Ok(RAW_EVENTS.to_vec()),
+                ..ReadAllEvents::default()
+            },
+            acquire_lease_result: Ok(false),
+            ..MockConnection::default()
+        };
+        let pool = MockPool {
+            get_result: Ok(connection),
+        };
+        let reactor = PostgresReactor::new(pool).with_lease(lease());
+
+        assert_eq!(
+            0,
+            run_until_idle(reactor, NonTransactional(MockReaction::default())).unwrap()
+        );
+    }
+
+    #[test]
+    fn goes_on_standby_when_lease_is_taken_over() {
+        let second_event = RawEvent {
+            event_id: EventNumber::new(2).unwrap(),
+            ..RAW_EVENT.clone()
+        };
+
+        for &transactional in &[false, true] {
+            let connection = MockConnection {
+                read_all_events_data: ReadAllEvents {
+                    result: Ok(vec![RAW_EVENT.clone(), second_event.clone()]),
+                    ..ReadAllEvents::default()
+                },
+                save_since_data: SaveSince {
+                    result: Ok(false),
+                    ..SaveSince::default()
+                },
+                ..MockConnection::default()
+            };
+            let reactor = PostgresReactor::new(MockPool {
+                get_result: Ok(connection.clone()),
+            })
+            .with_lease(lease());
+
+            let event_count = if transactional {
+                run_until_idle(reactor, Transactional(MockTransactionalReaction::default()))
+                    .map_err(|err| err.to_string())
+            } else {
+                run_until_idle(reactor, NonTransactional(MockReaction::default()))
+                    .map_err(|err| err.to_string())
+            };
+
+            assert_eq!(0, event_count.unwrap());
+            assert_eq!(vec![RAW_EVENT.event_id], *connection.saved_since.lock());
+            assert_eq!(
+                vec![String::from("node-1")],
+                *connection.released_leases.lock()
+            );
+        }
+    }
+
+    #[test]
+    fn releases_lease_when_stopped_by_an_error() {
+        let (pool, connection) = failing_reaction_pool();
+        let reactor = PostgresReactor::new(pool).with_lease(lease());
+
+        assert_eq!(
+            "React error during reaction: react error",
+            run_until_idle(reactor, NonTransactional(failing_reaction()))
+                .err()
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            vec![String::from("node-1")],
+            *connection.released_leases.lock()
+        );
+    }
+
+    #[test]
+    fn retry_backoff_is_capped_below_the_lease_duration() {
+        let reactor = PostgresReactor::new(MockPool {
+            get_result: Ok(MockConnection::default()),
+        })
+        .with_error_policy(ErrorPolicy::Retry {
+            max_retries: 10,
+            initial_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(600),
+        })
+        .with_lease(lease());
+
+        assert_eq!(Duration::from_secs(10), reactor.retry_backoff(0));
+        assert_eq!(Duration::from_secs(15), reactor.retry_backoff(1));
+    }
+
+    fn ok_pool(expected_query: String, expected_params: String) -> MockPool {
+        let connection = MockConnection {
+            read_all_events_data: ReadAllEvents {
+                expected_query: Some(expected_query),
+                expected_params: Some(expected_params),
+                result: Ok(RAW_EVENTS.to_vec()),
+                ..ReadAllEvents::default()
+            },
+            ..MockConnection::default()
+        };
+
+        MockPool {
+            get_result: Ok(connection),
+        }
+    }
+
+    fn test_reaction(
+        pool: MockPool,
+        reaction: MockReaction,
+    ) -> Result<usize, ReactorError<String, impl CqrsError, impl CqrsError>> {
+        test_reaction_with_wake_strategy(pool, reaction, WakeStrategy::Poll)
+    }
+
+    fn test_reaction_with_wake_strategy(
+        pool: MockPool,
+        reaction: MockReaction,
+        wake_strategy: WakeStrategy,
+    ) -> Result<usize, ReactorError<String, impl CqrsError, impl CqrsError>> {
+        run_until_idle(
+            PostgresReactor::new(pool).with_wake_strategy(wake_strategy),
+            NonTransactional(reaction),
+        )
+    }
+
+    /// Runs a reaction until it has reacted to all of the mock connection's events, or is waiting on standby, and then
+    /// stops it.
+    fn run_until_idle<R>(
+        reactor: PostgresReactor<MockPool>,
+        reaction: R,
+    ) -> Result<usize, ReactorError<R::Error, impl CqrsError, impl CqrsError>>
+    where
+        R: ReactionStep + Send + 'static,
+    {
+        let idle = match reactor.pool.get_result {
+            Ok(ref connection) => Arc::clone(&connection.idle),
+            Err(_) => Arc::default(),
+        };
+        let started = reactor.stop_count();
+        let local_reactor = Arc::new(reactor);
+        let thread_reactor = Arc::clone(&local_reactor);
+        let thread_idle = Arc::clone(&idle);
+
+        let handle = thread::spawn(move || {
+            let result = thread_reactor.run_reaction(reaction, started);
+            thread_idle.notify();
+            result
+        });
+
+        idle.wait();
+        local_reactor.stop_reaction();
 
         handle.join().unwrap()
     }
+
+    /// Waits for a condition to hold, failing the test if it does not hold within [TEST_TIMEOUT].
+    fn wait_until<F: Fn() -> bool>(condition: F) {
+        let deadline = Instant::now() + TEST_TIMEOUT;
+        while !condition() {
+            assert!(Instant::now() < deadline, "condition did not hold in time");
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn can_list_reactions() {
+        let position = ReactionPosition {
+            reaction_name: String::from("Mock"),
+            event_id: EventNumber::new(2).unwrap(),
+            event_id_distance: 3,
+        };
+        let connection = MockConnection {
+            reaction_positions: vec![position.clone()],
+            ..MockConnection::default()
+        };
+        let reactor = PostgresReactor::new(MockPool {
+            get_result: Ok(connection),
+        });
+
+        assert_eq!(vec![position], reactor.reactions().unwrap());
+    }
+
+    #[test]
+    fn can_reset_reaction() {
+        let connection = MockConnection::default();
+        let reactor = PostgresReactor::new(MockPool {
+            get_result: Ok(connection.clone()),
+        });
+
+        reactor.reset_reaction("Mock").unwrap();
+
+        assert_eq!(vec![String::from("Mock")], *connection.deleted_since.lock());
+    }
+
+    #[test]
+    fn can_rewind_reaction() {
+        let connection = MockConnection {
+            save_since_data: SaveSince {
+                expected_event_id: Some(EventNumber::new(2).unwrap()),
+                ..SaveSince::default()
+            },
+            ..MockConnection::default()
+        };
+        let reactor = PostgresReactor::new(MockPool {
+            get_result: Ok(connection),
+        });
+
+        reactor
+            .rewind_reaction("Mock", EventNumber::new(2).unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn resets_and_rewinds_the_reactors_partition_of_a_reaction() {
+        let connection = MockConnection {
+            save_since_data: SaveSince {
+                expected_reaction_name: String::from("Mock[1/4]"),
+                expected_event_id: Some(EventNumber::new(2).unwrap()),
+                ..SaveSince::default()
+            },
+            ..MockConnection::default()
+        };
+        let reactor = PostgresReactor::new(MockPool {
+            get_result: Ok(connection.clone()),
+        })
+        .with_partition(Partition::new(1, 4));
+
+        reactor.reset_reaction("Mock").unwrap();
+        reactor
+            .rewind_reaction("Mock", EventNumber::new(2).unwrap())
+            .unwrap();
+
+        assert_eq!(
+            vec![String::from("Mock[1/4]")],
+            *connection.deleted_since.lock()
+        );
+        assert_eq!(
+            vec![EventNumber::new(2).unwrap()],
+            *connection.saved_since.lock()
+        );
+    }
+
+    #[test]
+    fn can_spawn_reaction_and_stop_it_with_its_handle() {
+        let connection = MockConnection {
+            read_all_events_data: ReadAllEvents {
+                result: Ok(RAW_EVENTS.to_vec()),
+                ..ReadAllEvents::default()
+            },
+            ..MockConnection::default()
+        };
+        let handle = PostgresReactor::new(MockPool {
+            get_result: Ok(connection.clone()),
+        })
+        .spawn_reaction(MockReaction::default());
+
+        connection.idle.wait();
+
+        assert_eq!(2, handle.stop_and_join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn can_restart_a_stopped_reactor() {
+        let connection = MockConnection {
+            read_all_events_data: ReadAllEvents {
+                result: Ok(RAW_EVENTS.to_vec()),
+                ..ReadAllEvents::default()
+            },
+            ..MockConnection::default()
+        };
+        let reactor = PostgresReactor::new(MockPool {
+            get_result: Ok(connection),
+        });
+
+        reactor.stop_reaction();
+
+        assert_eq!(
+            2,
+            run_until_idle(reactor, NonTransactional(MockReaction::default())).unwrap()
+        );
+    }
+
+    #[test]
+    fn stopping_a_spawned_reaction_before_it_starts_stops_it() {
+        let handle = PostgresReactor::new(MockPool {
+            get_result: Ok(MockConnection::default()),
+        })
+        .spawn_reaction(PatientReaction);
+
+        assert_eq!(0, handle.stop_and_join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn stopping_interrupts_waiting_for_new_events() {
+        for &wake_strategy in &[WakeStrategy::Poll, WakeStrategy::Notify] {
+            let connection = MockConnection::default();
+            let handle: ReactionHandle<_, _> = PostgresReactor::new(MockPool {
+                get_result: Ok(connection.clone()),
+            })
+            .with_wake_strategy(wake_strategy)
+            .spawn_reaction(PatientReaction);
+
+            connection.idle.wait();
+            let stopping = Instant::now();
+
+            assert_eq!(0, handle.stop_and_join().unwrap().unwrap());
+            assert!(stopping.elapsed() < PatientReaction::interval());
+        }
+    }
+
+    #[test]
+    fn can_react_through_the_core_reactor_trait() {
+        fn react<T: Reactor + Send + Sync + 'static>(reactor: T, idle: Arc<Idle>) -> usize {
+            let local_reactor = Arc::new(reactor);
+            let thread_reactor = Arc::clone(&local_reactor);
+            let thread_idle = Arc::clone(&idle);
+
+            let handle = thread::spawn(move || {
+                let result = thread_reactor.start_reaction(MockReaction::default());
+                thread_idle.notify();
+                result
+            });
+
+            idle.wait();
+            local_reactor.stop_reaction();
+
+            handle.join().unwrap().unwrap()
+        }
+
+        let connection = MockConnection {
+            read_all_events_data: ReadAllEvents {
+                result: Ok(RAW_EVENTS.to_vec()),
+                ..ReadAllEvents::default()
+            },
+            ..MockConnection::default()
+        };
+        let idle = Arc::clone(&connection.idle);
+
+        assert_eq!(
+            2,
+            react(
+                PostgresReactor::new(MockPool {
+                    get_result: Ok(connection),
+                }),
+                idle
+            )
+        );
+    }
 }
@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+/// A policy for which snapshots of an entity should be retained in the `snapshots` table.
+///
+/// The latest snapshot of an entity is always retained, regardless of policy.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum SnapshotRetention {
+    /// Retains every snapshot that has been persisted.
+    KeepAll,
+
+    /// Retains only the given number of most recent snapshots for each entity.
+    ///
+    /// A value of zero is treated the same as a value of one.
+    KeepLatest(u32),
+
+    /// Retains only the snapshots that were persisted within the given duration, in addition to the latest snapshot
+    /// for each entity.
+    KeepNewerThan(Duration),
+}
+
+impl Default for SnapshotRetention {
+    #[inline]
+    fn default() -> Self {
+        SnapshotRetention::KeepAll
+    }
+}
@@ -1,18 +1,37 @@
 use crate::{
-    error::{LoadError, PersistError},
-    util::{BorrowedJson, Json, RawJsonPersist, RawJsonRead, Sequence},
+    error::{LoadError, MigrationError, PersistError, UnitOfWorkError},
+    format::PayloadFormat,
+    migrations,
+    raw::{upcast, GlobalOrdering, RawPostgresStore},
+    retention::SnapshotRetention,
+    util::{as_seconds, BorrowedJson, Json, RawJsonPersist, RawJsonRead, Sequence},
 };
 use cqrs_core::{
-    Aggregate, AggregateEvent, AggregateId, Before, DeserializableEvent, EventNumber, EventSink,
-    EventSource, NeverSnapshot, Precondition, SerializableEvent, Since, SnapshotRecommendation,
-    SnapshotSink, SnapshotSource, SnapshotStrategy, Version, VersionedAggregate, VersionedEvent,
+    Aggregate, AggregateEvent, AggregateId, Before, DeserializableEvent, EntityPreconditionFailed,
+    EventNumber, EventSink, EventSource, EventUpcasters, EventWithMetadataSource,
+    GlobalEventSource, NeverSnapshot, Precondition, RawEvent, ReverseEventSource,
+    SerializableEvent, Since, SnapshotRecommendation, SnapshotSink, SnapshotSource,
+    SnapshotStrategy, UnitOfWork, UnitOfWorkSink, Version, VersionedAggregate, VersionedEvent,
     VersionedEventWithMetadata,
 };
-use fallible_iterator::{FallibleIterator, IntoFallibleIterator};
+use fallible_iterator::FallibleIterator;
 use num_traits::FromPrimitive;
-use postgres::Connection;
+use postgres::{transaction::Transaction, Connection};
 use serde::{de::DeserializeOwned, Serialize};
-use std::{fmt, marker::PhantomData};
+use std::{fmt, marker::PhantomData, sync::Arc};
+use void::Void;
+
+/// The columns selected when reading the events of an entity, shared by the forward and reverse readers.
+const ENTITY_EVENT_COLUMNS: &str =
+    "sequence, event_type, COALESCE(binary_payload, convert_to(payload::text, 'UTF8')), \
+     schema_version, payload_format, metadata";
+
+/// The direction in which the events of an entity are read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ReadDirection {
+    Forward,
+    Reverse,
+}
 
 /// A PostgreSQL storage backend.
 #[derive(Clone)]
@@ -24,6 +43,10 @@ where
 {
     conn: &'conn Connection,
     snapshot_strategy: S,
+    snapshot_retention: SnapshotRetention,
+    global_ordering: GlobalOrdering,
+    upcasters: Arc<EventUpcasters>,
+    payload_format: PayloadFormat,
     _phantom: PhantomData<&'conn (A, E, M)>,
 }
 
@@ -37,6 +60,10 @@ where
         f.debug_struct("PostgresStore")
             .field("conn", &*self.conn)
             .field("strategy", &self.snapshot_strategy)
+            .field("retention", &self.snapshot_retention)
+            .field("global_ordering", &self.global_ordering)
+            .field("upcasters", &self.upcasters)
+            .field("payload_format", &self.payload_format)
             .field("phantom", &self._phantom)
             .finish()
     }
@@ -48,71 +75,38 @@ where
     E: AggregateEvent<A>,
     S: SnapshotStrategy + Default,
 {
-    const DB_VERSION: u32 = 1;
-
     /// Constructs a transient store based on a provided PostgreSQL connection using the default snapshot strategy.
     pub fn new(conn: &'conn Connection) -> Self {
         PostgresStore {
             conn,
             snapshot_strategy: S::default(),
+            snapshot_retention: SnapshotRetention::default(),
+            global_ordering: GlobalOrdering::default(),
+            upcasters: Arc::default(),
+            payload_format: PayloadFormat::default(),
             _phantom: PhantomData,
         }
     }
 
-    /// Constructs a transient store based on a provided PostgreSQL connection and snapshot strategy.
-    pub fn with_snapshot_strategy(conn: &'conn Connection, snapshot_strategy: S) -> Self {
-        PostgresStore {
-            conn,
-            snapshot_strategy,
-            _phantom: PhantomData,
-        }
-    }
-
-    /// Creates the base set of tables required to support the CQRS system.
-    pub fn create_tables(&self) -> Result<(), postgres::Error> {
-        self.conn
-            .batch_execute(include_str!("migrations/00_create_migrations.sql"))?;
-
-        let current_version: i32 = self
-            .conn
-            .query("SELECT MAX(version) from migrations", &[])?
-            .iter()
-            .next()
-            .and_then(|r| r.get(0))
-            .unwrap_or_default();
-
-        if current_version < 1 {
-            self.conn
-                .batch_execute(include_str!("migrations/01_create_tables.sql"))?;
-        }
-
-        Ok(())
+    /// Creates the base set of tables required to support the CQRS system, applying any pending migrations.
+    ///
+    /// Fails without applying any migrations if a previously applied migration has since been modified.
+    pub fn create_tables(&self) -> Result<(), MigrationError> {
+        migrations::run_migrations(self.conn)
     }
 
     /// Checks to see if the database is the latest version as seen by the current executable..
     pub fn is_latest(&self) -> Result<bool, postgres::Error> {
-        let current_version: i32 = self
-            .conn
-            .query("SELECT MAX(version) from migrations", &[])?
-            .iter()
-            .next()
-            .and_then(|r| r.get(0))
-            .unwrap_or_default();
+        let current_version = migrations::current_version(self.conn)?;
 
-        Ok(Self::DB_VERSION == current_version as u32)
+        Ok(migrations::latest_version() == current_version)
     }
 
     /// Checks to see if the database is compatible with the current executable.
     pub fn is_compatible(&self) -> Result<bool, postgres::Error> {
-        let current_version: i32 = self
-            .conn
-            .query("SELECT MAX(version) from migrations", &[])?
-            .iter()
-            .next()
-            .and_then(|r| r.get(0))
-            .unwrap_or_default();
+        let current_version = migrations::current_version(self.conn)?;
 
-        Ok(Self::DB_VERSION >= current_version as u32)
+        Ok(migrations::latest_version() >= current_version)
     }
 
     /// Gets the total number of entities of this type in the store.
@@ -281,7 +275,14 @@ where
         ])?;
         Ok(rows.iter().map(|r| r.get(0)).collect())
     }
+}
 
+impl<'conn, A, E, M, S> PostgresStore<'conn, A, E, M, S>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + DeserializableEvent,
+    S: SnapshotStrategy,
+{
     /// Reads events and associated metadata from the event source for a given identifier.
     ///
     /// Only loads events after the event number provided in `since` (See [Since]), and will only load a maximum of
@@ -297,7 +298,6 @@ where
     >
     where
         I: AggregateId<A>,
-        E: DeserializableEvent,
         M: for<'de> serde::Deserialize<'de>,
     {
         let last_sequence = match since {
@@ -305,78 +305,13 @@ where
             cqrs_core::Since::Event(x) => x.get(),
         } as i64;
 
-        let events;
-        let trans = self
-            .conn
-            .transaction_with(postgres::transaction::Config::default().read_only(true))?;
-
-        let handle_row = |row: postgres::rows::Row| {
-            let sequence: Sequence = row.get(0);
-            let event_type: String = row.get(1);
-            let raw: RawJsonRead = row.get(2);
-            let metadata: Json<M> = row.get(3);
-            let event = E::deserialize_event_from_buffer(&raw.0, &event_type)
-                .map_err(LoadError::DeserializationError)?
-                .ok_or_else(|| LoadError::UnknownEventType(event_type.clone()))?;
-            log::trace!(
-                "entity {}: loaded event; sequence: {}, type: {}",
-                id.as_str(),
-                sequence.0,
-                event_type
-            );
-            Ok(VersionedEventWithMetadata {
-                sequence: sequence.0,
-                event,
-                metadata: metadata.0,
-            })
-        };
-
-        let stmt;
-        {
-            let mut rows;
-            if let Some(max_count) = max_count {
-                stmt = trans.prepare_cached(
-                    "SELECT sequence, event_type, payload, metadata \
-                     FROM events \
-                     WHERE aggregate_type = $1 AND entity_id = $2 AND sequence > $3 \
-                     ORDER BY sequence ASC \
-                     LIMIT $4",
-                )?;
-                rows = stmt.lazy_query(
-                    &trans,
-                    &[
-                        &A::aggregate_type(),
-                        &id.as_str(),
-                        &last_sequence,
-                        &(max_count.min(i64::max_value() as u64) as i64),
-                    ],
-                    100,
-                )?;
-            } else {
-                stmt = trans.prepare_cached(
-                    "SELECT sequence, event_type, payload, metadata \
-                     FROM events \
-                     WHERE aggregate_type = $1 AND entity_id = $2 AND sequence > $3 \
-                     ORDER BY sequence ASC",
-                )?;
-                rows = stmt.lazy_query(
-                    &trans,
-                    &[&A::aggregate_type(), &id.as_str(), &last_sequence],
-                    100,
-                )?;
-            }
-
-            let (lower, upper) = rows.size_hint();
-            let cap = upper.unwrap_or(lower);
-            let mut inner_events = Vec::with_capacity(cap);
-
-            while let Some(row) = rows.next()? {
-                inner_events.push(handle_row(row));
-            }
-            events = inner_events;
-        }
-
-        trans.commit()?;
+        let events = self.read_entity_rows(
+            id,
+            ReadDirection::Forward,
+            last_sequence,
+            max_count,
+            |row| self.event_with_metadata_from_row(id, &row),
+        )?;
 
         log::trace!("entity {}: read {} events", id.as_str(), events.len());
 
@@ -399,7 +334,6 @@ where
     >
     where
         I: AggregateId<A>,
-        E: DeserializableEvent,
         M: for<'de> serde::Deserialize<'de>,
     {
         let last_sequence = match before {
@@ -407,82 +341,274 @@ where
             Before::Event(x) => x.get() as i64,
         };
 
-        let events;
+        let events = self.read_entity_rows(
+            id,
+            ReadDirection::Reverse,
+            last_sequence,
+            max_count,
+            |row| self.event_with_metadata_from_row(id, &row),
+        )?;
+
+        log::trace!("entity {}: read {} events", id.as_str(), events.len());
+
+        Ok(Some(events))
+    }
+
+    /// Reads the rows of the events of an entity after (or, in reverse, before) the given sequence number within a
+    /// read-only transaction, handling each row in turn.
+    ///
+    /// Rows hold the columns listed in [ENTITY_EVENT_COLUMNS].
+    fn read_entity_rows<I, T, F>(
+        &self,
+        id: &I,
+        direction: ReadDirection,
+        sequence: i64,
+        max_count: Option<u64>,
+        mut handle_row: F,
+    ) -> Result<Vec<T>, LoadError<E::Error>>
+    where
+        I: AggregateId<A>,
+        F: FnMut(postgres::rows::Row) -> T,
+    {
+        let (comparison, order) = match direction {
+            ReadDirection::Forward => (">", "ASC"),
+            ReadDirection::Reverse => ("<", "DESC"),
+        };
+        let mut query = format!(
+            "SELECT {} \
+             FROM events \
+             WHERE aggregate_type = $1 AND entity_id = $2 AND sequence {} $3 \
+             ORDER BY sequence {}",
+            ENTITY_EVENT_COLUMNS, comparison, order
+        );
+
         let trans = self
             .conn
             .transaction_with(postgres::transaction::Config::default().read_only(true))?;
 
-        let handle_row = |row: postgres::rows::Row| {
-            let sequence: Sequence = row.get(0);
-            let event_type: String = row.get(1);
-            let raw: RawJsonRead = row.get(2);
-            let metadata: Json<M> = row.get(3);
-            let event = E::deserialize_event_from_buffer(&raw.0, &event_type)
-                .map_err(LoadError::DeserializationError)?
-                .ok_or_else(|| LoadError::UnknownEventType(event_type.clone()))?;
-            log::trace!(
-                "entity {}: loaded event; sequence: {}, type: {}",
-                id.as_str(),
-                sequence.0,
-                event_type
-            );
-            Ok(VersionedEventWithMetadata {
-                sequence: sequence.0,
-                event,
-                metadata: metadata.0,
-            })
-        };
-
-        let stmt;
-        {
-            let mut rows;
-            if let Some(max_count) = max_count {
-                stmt = trans.prepare_cached(
-                    "SELECT sequence, event_type, payload, metadata \
-                     FROM events \
-                     WHERE aggregate_type = $1 AND entity_id = $2 AND sequence < $3 \
-                     ORDER BY sequence DESC \
-                     LIMIT $4",
-                )?;
-                rows = stmt.lazy_query(
-                    &trans,
-                    &[
-                        &A::aggregate_type(),
-                        &id.as_str(),
-                        &last_sequence,
-                        &(max_count.min(i64::max_value() as u64) as i64),
-                    ],
-                    100,
-                )?;
-            } else {
-                stmt = trans.prepare_cached(
-                    "SELECT sequence, event_type, payload, metadata \
-                     FROM events \
-                     WHERE aggregate_type = $1 AND entity_id = $2 AND sequence < $3 \
-                     ORDER BY sequence DESC",
-                )?;
-                rows = stmt.lazy_query(
-                    &trans,
-                    &[&A::aggregate_type(), &id.as_str(), &last_sequence],
-                    100,
-                )?;
+        let events = {
+            let max_count = max_count.and_then(i64::from_u64);
+            if max_count.is_some() {
+                query.push_str(" LIMIT $4");
+            }
+            let stmt = trans.prepare_cached(&query)?;
+            let aggregate_type = A::aggregate_type();
+            let entity_id = id.as_str();
+            let mut params: Vec<&dyn postgres::types::ToSql> =
+                vec![&aggregate_type, &entity_id, &sequence];
+            if let Some(ref max_count) = max_count {
+                params.push(max_count);
             }
+            let mut rows = stmt.lazy_query(&trans, &params, 100)?;
 
             let (lower, upper) = rows.size_hint();
-            let cap = upper.unwrap_or(lower);
-            let mut inner_events = Vec::with_capacity(cap);
-
+            let mut events = Vec::with_capacity(upper.unwrap_or(lower));
             while let Some(row) = rows.next()? {
-                inner_events.push(handle_row(row));
+                events.push(handle_row(row));
             }
-            events = inner_events;
-        }
+            events
+        };
 
         trans.commit()?;
 
-        log::trace!("entity {}: read {} events", id.as_str(), events.len());
+        Ok(events)
+    }
 
-        Ok(Some(events))
+    /// Deserializes an event from a row holding the columns listed in [ENTITY_EVENT_COLUMNS].
+    fn event_from_row<I>(
+        &self,
+        id: &I,
+        row: &postgres::rows::Row,
+    ) -> Result<VersionedEvent<E>, LoadError<E::Error>>
+    where
+        I: AggregateId<A>,
+    {
+        let sequence: Sequence = row.get(0);
+        let event_type: String = row.get(1);
+        let raw: RawJsonRead = row.get(2);
+        let payload_format: String = row.get(4);
+        let event = deserialize_event(
+            &self.upcasters,
+            &event_type,
+            row.get(3),
+            &payload_format,
+            &raw.0,
+        )?;
+        log::trace!(
+            "entity {}: loaded event; sequence: {}, type: {}",
+            id.as_str(),
+            sequence.0,
+            event_type
+        );
+        Ok(VersionedEvent {
+            sequence: sequence.0,
+            event,
+        })
+    }
+
+    /// Deserializes an event and its metadata from a row holding the columns listed in [ENTITY_EVENT_COLUMNS].
+    fn event_with_metadata_from_row<I>(
+        &self,
+        id: &I,
+        row: &postgres::rows::Row,
+    ) -> Result<VersionedEventWithMetadata<E, M>, LoadError<E::Error>>
+    where
+        I: AggregateId<A>,
+        M: for<'de> serde::Deserialize<'de>,
+    {
+        let metadata: Json<M> = row.get(5);
+        let event = self.event_from_row(id, row)?;
+        Ok(VersionedEventWithMetadata {
+            sequence: event.sequence,
+            event: event.event,
+            metadata: metadata.0,
+        })
+    }
+}
+
+impl<'conn, A, E, M, S> PostgresStore<'conn, A, E, M, S>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+    S: SnapshotStrategy,
+{
+    /// Constructs a transient store based on a provided PostgreSQL connection and snapshot strategy.
+    pub fn with_snapshot_strategy(conn: &'conn Connection, snapshot_strategy: S) -> Self {
+        PostgresStore {
+            conn,
+            snapshot_strategy,
+            snapshot_retention: SnapshotRetention::default(),
+            global_ordering: GlobalOrdering::default(),
+            upcasters: Arc::default(),
+            payload_format: PayloadFormat::default(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Sets the policy for which snapshots are retained when a new snapshot is persisted.
+    pub fn with_snapshot_retention(mut self, snapshot_retention: SnapshotRetention) -> Self {
+        self.snapshot_retention = snapshot_retention;
+        self
+    }
+
+    /// Sets the order in which events are read from the global event stream.
+    pub fn with_global_ordering(mut self, global_ordering: GlobalOrdering) -> Self {
+        self.global_ordering = global_ordering;
+        self
+    }
+
+    /// Sets the upcasters applied to event payloads persisted with older schema versions before they are deserialized.
+    ///
+    /// Newly appended events are recorded with their [SerializableEvent::schema_version].
+    pub fn with_upcasters(mut self, upcasters: impl Into<Arc<EventUpcasters>>) -> Self {
+        self.upcasters = upcasters.into();
+        self
+    }
+
+    /// Sets the format in which appended event payloads are serialized and persisted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the format is a [PayloadFormat::Binary] format named `"json"`.
+    pub fn with_payload_format(mut self, payload_format: PayloadFormat) -> Self {
+        payload_format.assert_valid();
+        self.payload_format = payload_format;
+        self
+    }
+
+    /// Removes the snapshots of a given entity that are not retained under the current retention policy.
+    ///
+    /// Returns the number of snapshots removed.
+    pub fn prune_snapshots<I>(&self, id: &I) -> Result<u64, postgres::Error>
+    where
+        I: AggregateId<A>,
+    {
+        let modified_count = match self.snapshot_retention {
+            SnapshotRetention::KeepAll => 0,
+            SnapshotRetention::KeepLatest(count) => {
+                let stmt = self.conn.prepare_cached(
+                    "DELETE FROM snapshots \
+                     WHERE snapshot_id IN ( \
+                       SELECT snapshot_id FROM ( \
+                         SELECT snapshot_id, \
+                           ROW_NUMBER() OVER (ORDER BY sequence DESC) AS position \
+                         FROM snapshots \
+                         WHERE aggregate_type = $1 AND entity_id = $2 \
+                       ) ranked \
+                       WHERE position > $3 \
+                     )",
+                )?;
+                stmt.execute(&[&A::aggregate_type(), &id.as_str(), &i64::from(count.max(1))])?
+            }
+            SnapshotRetention::KeepNewerThan(age) => {
+                let stmt = self.conn.prepare_cached(
+                    "DELETE FROM snapshots \
+                     WHERE snapshot_id IN ( \
+                       SELECT snapshot_id FROM ( \
+                         SELECT snapshot_id, timestamp, \
+                           ROW_NUMBER() OVER (ORDER BY sequence DESC) AS position \
+                         FROM snapshots \
+                         WHERE aggregate_type = $1 AND entity_id = $2 \
+                       ) ranked \
+                       WHERE position > 1 AND timestamp < CURRENT_TIMESTAMP - make_interval(secs => $3) \
+                     )",
+                )?;
+                stmt.execute(&[&A::aggregate_type(), &id.as_str(), &as_seconds(age)])?
+            }
+        };
+
+        log::trace!(
+            "entity {}: pruned {} snapshots",
+            id.as_str(),
+            modified_count
+        );
+        Ok(modified_count)
+    }
+
+    /// Removes the snapshots of all entities of this type that are not retained under the current retention policy.
+    ///
+    /// Returns the number of snapshots removed.
+    pub fn prune_all_snapshots(&self) -> Result<u64, postgres::Error> {
+        let modified_count = match self.snapshot_retention {
+            SnapshotRetention::KeepAll => 0,
+            SnapshotRetention::KeepLatest(count) => {
+                let stmt = self.conn.prepare_cached(
+                    "DELETE FROM snapshots \
+                     WHERE snapshot_id IN ( \
+                       SELECT snapshot_id FROM ( \
+                         SELECT snapshot_id, \
+                           ROW_NUMBER() OVER (PARTITION BY entity_id ORDER BY sequence DESC) AS position \
+                         FROM snapshots \
+                         WHERE aggregate_type = $1 \
+                       ) ranked \
+                       WHERE position > $2 \
+                     )",
+                )?;
+                stmt.execute(&[&A::aggregate_type(), &i64::from(count.max(1))])?
+            }
+            SnapshotRetention::KeepNewerThan(age) => {
+                let stmt = self.conn.prepare_cached(
+                    "DELETE FROM snapshots \
+                     WHERE snapshot_id IN ( \
+                       SELECT snapshot_id FROM ( \
+                         SELECT snapshot_id, timestamp, \
+                           ROW_NUMBER() OVER (PARTITION BY entity_id ORDER BY sequence DESC) AS position \
+                         FROM snapshots \
+                         WHERE aggregate_type = $1 \
+                       ) ranked \
+                       WHERE position > 1 AND timestamp < CURRENT_TIMESTAMP - make_interval(secs => $2) \
+                     )",
+                )?;
+                stmt.execute(&[&A::aggregate_type(), &as_seconds(age)])?
+            }
+        };
+
+        log::trace!(
+            "aggregate {}: pruned {} snapshots",
+            A::aggregate_type(),
+            modified_count
+        );
+        Ok(modified_count)
     }
 }
 
@@ -507,66 +633,151 @@ where
     {
         let trans = self.conn.transaction()?;
 
-        let check_stmt = trans.prepare_cached(
-            "SELECT MAX(sequence) FROM events WHERE aggregate_type = $1 AND entity_id = $2",
+        let first_sequence = append_events_in_transaction::<A, _, _>(
+            &trans,
+            self.payload_format,
+            id.as_str(),
+            events,
+            precondition,
+            &metadata,
         )?;
 
-        let result = check_stmt.query(&[&A::aggregate_type(), &id.as_str()])?;
-        let current_version = result.iter().next().and_then(|r| {
-            let max_sequence: Option<Sequence> = r.get(0);
-            max_sequence.map(|x| Version::from(x.0))
-        });
+        trans.commit()?;
 
-        log::trace!(
-            "entity {}: current version: {:?}",
-            id.as_str(),
-            current_version
-        );
+        Ok(first_sequence)
+    }
+}
 
-        if events.is_empty() {
-            return Ok(current_version.unwrap_or_default().next_event());
-        }
+impl<'conn, A, E, M, S> UnitOfWorkSink<A, E, M> for PostgresStore<'conn, A, E, M, S>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + SerializableEvent + fmt::Debug,
+    M: Serialize + fmt::Debug,
+    S: SnapshotStrategy,
+{
+    type Error = UnitOfWorkError<<E as SerializableEvent>::Error>;
+
+    fn commit(&self, unit_of_work: UnitOfWork<A, E, M>) -> Result<Vec<EventNumber>, Self::Error> {
+        let trans = self.conn.transaction()?;
 
-        if let Some(precondition) = precondition {
-            precondition.verify(current_version)?;
+        let mut first_sequences = Vec::with_capacity(unit_of_work.appends().len());
+        for append in unit_of_work.into_appends() {
+            let first_sequence = append_events_in_transaction::<A, _, _>(
+                &trans,
+                self.payload_format,
+                &append.entity_id,
+                &append.events,
+                append.precondition,
+                &append.metadata,
+            )
+            .map_err(|err| match err {
+                PersistError::Postgres(err) => UnitOfWorkError::Postgres(err),
+                PersistError::PreconditionFailed(precondition) => {
+                    UnitOfWorkError::PreconditionFailed(EntityPreconditionFailed {
+                        entity_id: append.entity_id.clone(),
+                        precondition,
+                    })
+                }
+                PersistError::SerializationError(err) => UnitOfWorkError::SerializationError(err),
+                PersistError::UnsupportedPayloadFormat(format) => {
+                    UnitOfWorkError::UnsupportedPayloadFormat(format)
+                }
+            })?;
+            first_sequences.push(first_sequence);
         }
 
-        log::trace!("entity {}: precondition satisfied", id.as_str());
+        trans.commit()?;
 
-        let first_sequence = current_version.unwrap_or_default().next_event();
-        let mut next_sequence = Version::Number(first_sequence);
-        let mut buffer = Vec::with_capacity(128);
+        log::trace!("committed unit of work; appends: {}", first_sequences.len());
 
-        let stmt = trans.prepare_cached(
-            "INSERT INTO events (aggregate_type, entity_id, sequence, event_type, payload, metadata, timestamp) \
-            VALUES ($1, $2, $3, $4, $5, $6, CURRENT_TIMESTAMP)",
-        )?;
-        for event in events {
-            buffer.clear();
-            event
-                .serialize_event_to_buffer(&mut buffer)
-                .map_err(PersistError::SerializationError)?;
-            let modified_count = stmt.execute(&[
-                &A::aggregate_type(),
-                &id.as_str(),
-                &(next_sequence.get() as i64),
-                &event.event_type(),
-                &RawJsonPersist(&buffer),
-                &BorrowedJson(&metadata),
-            ])?;
-            debug_assert!(modified_count > 0);
-            log::trace!(
-                "entity {}: inserted event; sequence: {}",
-                id.as_str(),
-                next_sequence
-            );
-            next_sequence.incr();
-        }
+        Ok(first_sequences)
+    }
+}
 
-        trans.commit()?;
+/// Appends events to the event stream of an entity within an existing transaction, verifying the precondition against
+/// the current version of the entity as seen by that transaction.
+fn append_events_in_transaction<A, E, M>(
+    trans: &Transaction,
+    payload_format: PayloadFormat,
+    entity_id: &str,
+    events: &[E],
+    precondition: Option<Precondition>,
+    metadata: &M,
+) -> Result<EventNumber, PersistError<<E as SerializableEvent>::Error>>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + SerializableEvent + fmt::Debug,
+    M: Serialize + fmt::Debug,
+{
+    let check_stmt = trans.prepare_cached(
+        "SELECT MAX(sequence) FROM events WHERE aggregate_type = $1 AND entity_id = $2",
+    )?;
+
+    let result = check_stmt.query(&[&A::aggregate_type(), &entity_id])?;
+    let current_version = result.iter().next().and_then(|r| {
+        let max_sequence: Option<Sequence> = r.get(0);
+        max_sequence.map(|x| Version::from(x.0))
+    });
+
+    log::trace!(
+        "entity {}: current version: {:?}",
+        entity_id,
+        current_version
+    );
+
+    if events.is_empty() {
+        return Ok(current_version.unwrap_or_default().next_event());
+    }
 
-        Ok(first_sequence)
+    if let Some(precondition) = precondition {
+        precondition.verify(current_version)?;
+    }
+
+    log::trace!("entity {}: precondition satisfied", entity_id);
+
+    let first_sequence = current_version.unwrap_or_default().next_event();
+    let mut next_sequence = Version::Number(first_sequence);
+    let mut buffer = Vec::with_capacity(128);
+
+    let stmt = trans.prepare_cached(
+        "INSERT INTO events (aggregate_type, entity_id, sequence, event_type, payload_format, payload, binary_payload, metadata, schema_version, timestamp) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, CURRENT_TIMESTAMP)",
+    )?;
+    for event in events {
+        buffer.clear();
+        let supported = event
+            .serialize_event_to_buffer_in_format(&mut buffer, payload_format.name())
+            .map_err(PersistError::SerializationError)?;
+        if !supported {
+            return Err(PersistError::UnsupportedPayloadFormat(
+                payload_format.name().to_owned(),
+            ));
+        }
+        let (payload, binary_payload) = match payload_format {
+            PayloadFormat::Json => (Some(RawJsonPersist(&buffer)), None),
+            PayloadFormat::Binary(_) => (None, Some(&buffer)),
+        };
+        let modified_count = stmt.execute(&[
+            &A::aggregate_type(),
+            &entity_id,
+            &(next_sequence.get() as i64),
+            &event.event_type(),
+            &payload_format.name(),
+            &payload,
+            &binary_payload,
+            &BorrowedJson(metadata),
+            &i64::from(event.schema_version()),
+        ])?;
+        debug_assert!(modified_count > 0);
+        log::trace!(
+            "entity {}: inserted event; sequence: {}",
+            entity_id,
+            next_sequence
+        );
+        next_sequence.incr();
     }
+
+    Ok(first_sequence)
 }
 
 impl<'conn, A, E, M, S> EventSource<A, E> for PostgresStore<'conn, A, E, M, S>
@@ -592,71 +803,53 @@ where
             cqrs_core::Since::Event(x) => x.get(),
         } as i64;
 
-        let trans = self
-            .conn
-            .transaction_with(postgres::transaction::Config::default().read_only(true))?;
+        let events = self
+            .read_entity_rows(
+                id,
+                ReadDirection::Forward,
+                last_sequence,
+                max_count,
+                |row| self.event_from_row(id, &row),
+            )?
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
 
-        let handle_row = |row: postgres::rows::Row| {
-            let sequence: Sequence = row.get(0);
-            let event_type: String = row.get(1);
-            let raw: RawJsonRead = row.get(2);
-            let event = E::deserialize_event_from_buffer(&raw.0, &event_type)
-                .map_err(LoadError::DeserializationError)?
-                .ok_or_else(|| LoadError::UnknownEventType(event_type.clone()))?;
-            log::trace!(
-                "entity {}: loaded event; sequence: {}, type: {}",
-                id.as_str(),
-                sequence.0,
-                event_type
-            );
-            Ok(VersionedEvent {
-                sequence: sequence.0,
-                event,
-            })
-        };
+        log::trace!("entity {}: read {} events", id.as_str(), events.len());
 
-        let events: Vec<VersionedEvent<E>> =
-            if let Some(max_count) = max_count.and_then(i64::from_u64) {
-                let stmt = trans.prepare_cached(
-                    "SELECT sequence, event_type, payload \
-                     FROM events \
-                     WHERE aggregate_type = $1 AND entity_id = $2 AND sequence > $3 \
-                     ORDER BY sequence ASC \
-                     LIMIT $4",
-                )?;
-                let rows = stmt.lazy_query(
-                    &trans,
-                    &[
-                        &A::aggregate_type(),
-                        &id.as_str(),
-                        &last_sequence,
-                        &max_count,
-                    ],
-                    100,
-                )?;
-                rows.into_fallible_iterator()
-                    .map_err(LoadError::Postgres)
-                    .and_then(handle_row)
-                    .collect()?
-            } else {
-                let stmt = trans.prepare_cached(
-                    "SELECT sequence, event_type, payload \
-                     FROM events \
-                     WHERE aggregate_type = $1 AND entity_id = $2 AND sequence > $3 \
-                     ORDER BY sequence ASC",
-                )?;
-                let rows = stmt.lazy_query(
-                    &trans,
-                    &[&A::aggregate_type(), &id.as_str(), &last_sequence],
-                    100,
-                )?;
-                rows.into_fallible_iterator()
-                    .map_err(LoadError::Postgres)
-                    .and_then(handle_row)
-                    .collect()?
-            };
+        Ok(Some(events))
+    }
+}
 
-        trans.commit()?;
+impl<'conn, A, E, M, S> ReverseEventSource<A, E> for PostgresStore<'conn, A, E, M, S>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + DeserializableEvent,
+    S: SnapshotStrategy,
+{
+    fn read_events_reverse<I>(
+        &self,
+        id: &I,
+        before: Before,
+        max_count: Option<u64>,
+    ) -> Result<Option<Self::Events>, Self::Error>
+    where
+        I: AggregateId<A>,
+    {
+        let last_sequence = match before {
+            Before::EndOfStream => std::i64::MAX,
+            Before::Event(x) => x.get() as i64,
+        };
+
+        let events = self
+            .read_entity_rows(
+                id,
+                ReadDirection::Reverse,
+                last_sequence,
+                max_count,
+                |row| self.event_from_row(id, &row),
+            )?
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
 
         log::trace!("entity {}: read {} events", id.as_str(), events.len());
 
@@ -664,6 +857,58 @@ where
     }
 }
 
+impl<'conn, A, E, M, S> EventWithMetadataSource<A, E, M> for PostgresStore<'conn, A, E, M, S>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + DeserializableEvent,
+    M: DeserializeOwned,
+    S: SnapshotStrategy,
+{
+    type Error = LoadError<<E as DeserializableEvent>::Error>;
+    type Events = Vec<VersionedEventWithMetadata<E, M>>;
+
+    fn read_events_with_metadata<I>(
+        &self,
+        id: &I,
+        since: Since,
+        max_count: Option<u64>,
+    ) -> Result<Option<Self::Events>, Self::Error>
+    where
+        I: AggregateId<A>,
+    {
+        let events = PostgresStore::read_events_with_metadata(self, id, since, max_count)?;
+
+        match events {
+            Some(events) => events.into_iter().collect::<Result<_, _>>().map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<'conn, A, E, M, S> GlobalEventSource for PostgresStore<'conn, A, E, M, S>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+    S: SnapshotStrategy,
+{
+    type Error = LoadError<Void>;
+    type Events = Vec<RawEvent>;
+
+    fn read_all_events(
+        &self,
+        since: Since,
+        max_count: Option<u64>,
+    ) -> Result<Self::Events, Self::Error> {
+        GlobalEventSource::read_all_events(
+            &RawPostgresStore::new(self.conn)
+                .with_global_ordering(self.global_ordering)
+                .with_upcasters(&self.upcasters),
+            since,
+            max_count,
+        )
+    }
+}
+
 impl<'conn, A, E, M, S> SnapshotSink<A> for PostgresStore<'conn, A, E, M, S>
 where
     A: Aggregate + Serialize + fmt::Debug,
@@ -691,22 +936,27 @@ where
             return Ok(last_snapshot_version.unwrap_or_default());
         }
 
+        // A snapshot at the same version replaces one that could not be loaded.
         let stmt = self.conn.prepare_cached(
-            "INSERT INTO snapshots (aggregate_type, entity_id, sequence, payload) \
-             VALUES ($1, $2, $3, $4)",
+            "INSERT INTO snapshots (aggregate_type, entity_id, sequence, schema_version, payload) \
+             VALUES ($1, $2, $3, $4, $5) \
+             ON CONFLICT (aggregate_type, entity_id, sequence) DO UPDATE \
+             SET schema_version = EXCLUDED.schema_version, \
+                 payload = EXCLUDED.payload, \
+                 timestamp = CURRENT_TIMESTAMP",
         )?;
         let _modified_count = stmt.execute(&[
             &A::aggregate_type(),
             &id.as_str(),
             &(version.get() as i64),
+            &i64::from(A::snapshot_schema_version()),
             &Json(aggregate),
         ])?;
 
-        // Clean up strategy for snapshots?
-        //        let stmt = self.conn.prepare_cached("DELETE FROM snapshots WHERE aggregate_type = $1 AND entity_id = $2 AND sequence < $3")?;
-        //        let _modified_count = stmt.execute(&[&A::aggregate_type(), &id.as_str(), &(version.get() as i64)])?;
-
         log::trace!("entity {}: persisted snapshot", id.as_str());
+
+        self.prune_snapshots(id)?;
+
         Ok(version)
     }
 }
@@ -724,7 +974,7 @@ where
         I: AggregateId<A>,
     {
         let stmt = self.conn.prepare_cached(
-            "SELECT sequence, payload \
+            "SELECT sequence, schema_version, payload \
              FROM snapshots \
              WHERE aggregate_type = $1 AND entity_id = $2 \
              ORDER BY sequence DESC \
@@ -733,15 +983,226 @@ where
         let rows = stmt.query(&[&A::aggregate_type(), &id.as_str()])?;
         if let Some(row) = rows.iter().next() {
             let sequence: Sequence = row.get(0);
-            let raw: Json<A> = row.get(1);
-            log::trace!("entity {}: loaded snapshot", id.as_str());
-            Ok(Some(VersionedAggregate {
-                version: Version::from(sequence.0),
-                payload: raw.0,
-            }))
+            let schema_version: i64 = row.get(1);
+            let raw: RawJsonRead = row.get(2);
+            if let Some(payload) = deserialize_snapshot(schema_version, &raw.0) {
+                log::trace!("entity {}: loaded snapshot", id.as_str());
+                Ok(Some(VersionedAggregate {
+                    version: Version::from(sequence.0),
+                    payload,
+                }))
+            } else {
+                log::warn!(
+                    "entity {}: snapshot with schema version {} is incompatible; rehydrating from events",
+                    id.as_str(),
+                    schema_version,
+                );
+                Ok(None)
+            }
         } else {
             log::trace!("entity {}: no snapshot found", id.as_str());
             Ok(None)
         }
     }
 }
+
+/// Upcasts an event payload persisted with the given schema version to the latest schema version of its event type,
+/// then deserializes it from the given payload format.
+fn deserialize_event<E>(
+    upcasters: &EventUpcasters,
+    event_type: &str,
+    schema_version: i64,
+    payload_format: &str,
+    payload: &[u8],
+) -> Result<E, LoadError<E::Error>>
+where
+    E: DeserializableEvent,
+{
+    let payload =
+        upcast(upcasters, event_type, schema_version, payload).map_err(LoadError::UpcastError)?;
+
+    E::deserialize_event_from_buffer_in_format(&payload, event_type, payload_format)
+        .map_err(LoadError::DeserializationError)?
+        .ok_or_else(|| LoadError::UnknownEventType(event_type.to_owned()))
+}
+
+/// Deserializes a snapshot payload persisted with the given schema version, upgrading it to the current schema
+/// version of the aggregate if necessary.
+///
+/// Returns `None` if the snapshot is incompatible with the aggregate.
+fn deserialize_snapshot<A>(schema_version: i64, payload: &[u8]) -> Option<A>
+where
+    A: Aggregate + DeserializeOwned,
+{
+    let current_version = A::snapshot_schema_version();
+
+    let result = if schema_version == i64::from(current_version) {
+        serde_json::from_slice(payload)
+    } else if schema_version >= 0 && schema_version < i64::from(current_version) {
+        let upgraded = A::upgrade_snapshot(schema_version as u32, payload)?;
+        serde_json::from_slice(&upgraded)
+    } else {
+        return None;
+    };
+
+    result
+        .map_err(|err| log::debug!("unable to deserialize snapshot: {}", err))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cqrs_todo_core::{domain::Description, events::Created, TodoEvent};
+    use serde::{Deserialize, Deserializer};
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Counter(u64);
+
+    impl<'de> Deserialize<'de> for Counter {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            u64::deserialize(deserializer).map(Counter)
+        }
+    }
+
+    impl Aggregate for Counter {
+        fn aggregate_type() -> &'static str {
+            "counter"
+        }
+
+        fn snapshot_schema_version() -> u32 {
+            2
+        }
+
+        fn upgrade_snapshot(schema_version: u32, payload: &[u8]) -> Option<Vec<u8>> {
+            if schema_version == 1 {
+                let value: serde_json::Value = serde_json::from_slice(payload).ok()?;
+                serde_json::to_vec(value.get("total")?).ok()
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn deserializes_snapshot_with_current_schema_version() {
+        assert_eq!(Some(Counter(3)), deserialize_snapshot(2, b"3"));
+    }
+
+    #[test]
+    fn upgrades_snapshot_with_older_schema_version() {
+        assert_eq!(Some(Counter(3)), deserialize_snapshot(1, br#"{"total":3}"#));
+    }
+
+    #[test]
+    fn rejects_incompatible_snapshots() {
+        assert_eq!(None, deserialize_snapshot::<Counter>(0, br#"{"total":3}"#));
+        assert_eq!(None, deserialize_snapshot::<Counter>(3, b"3"));
+        assert_eq!(None, deserialize_snapshot::<Counter>(2, br#"{"total":3}"#));
+    }
+
+    fn renamed_description_upcasters() -> EventUpcasters {
+        EventUpcasters::new().with_upcaster("todo_created", 0, |payload: &[u8]| {
+            let mut fields: serde_json::Map<String, serde_json::Value> =
+                serde_json::from_slice(payload)?;
+            if let Some(description) = fields.remove("description") {
+                fields.insert(String::from("initial_description"), description);
+            }
+            serde_json::to_vec(&fields)
+        })
+    }
+
+    #[test]
+    fn upcasts_events_before_deserializing() {
+        let created = Created {
+            initial_description: Description::new("Buy milk").unwrap(),
+        };
+        let mut fields = serde_json::to_value(&created).unwrap();
+        let fields = fields.as_object_mut().unwrap();
+        let description = fields.remove("initial_description").unwrap();
+        fields.insert(String::from("description"), description);
+        let payload = serde_json::to_vec(&fields).unwrap();
+
+        let upcasters = renamed_description_upcasters();
+        let event: TodoEvent =
+            deserialize_event(&upcasters, "todo_created", 0, "json", &payload).unwrap();
+        assert_eq!(TodoEvent::Created(created.clone()), event);
+
+        let payload = serde_json::to_vec(&created).unwrap();
+        let event: TodoEvent =
+            deserialize_event(&upcasters, "todo_created", 1, "json", &payload).unwrap();
+        assert_eq!(TodoEvent::Created(created), event);
+    }
+
+    #[test]
+    fn fails_to_load_events_with_unknown_schema_versions() {
+        let result = deserialize_event::<TodoEvent>(
+            &renamed_description_upcasters(),
+            "todo_created",
+            2,
+            "json",
+            b"{}",
+        );
+
+        match result {
+            Err(LoadError::UpcastError(err)) => assert_eq!(2, err.schema_version),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fails_to_load_events_with_out_of_range_schema_versions() {
+        for &schema_version in &[-1, i64::from(u32::max_value()) + 1] {
+            let result = deserialize_event::<TodoEvent>(
+                &renamed_description_upcasters(),
+                "todo_created",
+                schema_version,
+                "json",
+                b"{}",
+            );
+
+            match result {
+                Err(LoadError::UpcastError(err)) => assert!(err.reason.contains("out of range")),
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct FormatEvent(String);
+
+    impl cqrs_core::Event for FormatEvent {
+        fn event_type(&self) -> &'static str {
+            "format"
+        }
+    }
+
+    impl DeserializableEvent for FormatEvent {
+        type Error = Void;
+
+        fn deserialize_event_from_buffer(
+            _data: &[u8],
+            _event_type: &str,
+        ) -> Result<Option<Self>, Self::Error> {
+            Ok(None)
+        }
+
+        fn deserialize_event_from_buffer_in_format(
+            _data: &[u8],
+            _event_type: &str,
+            format: &str,
+        ) -> Result<Option<Self>, Self::Error> {
+            Ok(Some(FormatEvent(format.to_owned())))
+        }
+    }
+
+    #[test]
+    fn deserializes_events_in_their_recorded_format() {
+        let event: FormatEvent =
+            deserialize_event(&EventUpcasters::new(), "format", 0, "msgpack", b"").unwrap();
+
+        assert_eq!(FormatEvent(String::from("msgpack")), event);
+        assert_eq!("msgpack", PayloadFormat::Binary("msgpack").name());
+        assert_eq!("json", PayloadFormat::default().name());
+    }
+}
@@ -0,0 +1,430 @@
+//! > _Note: Experimental!_
+//!
+//! Types for running many reactions concurrently against a PostgreSQL event store.
+
+use crate::{
+    db_wrapper::DbPool,
+    raw::GlobalOrdering,
+    reactor::{
+        Batch, BatchReaction, ErrorPolicy, Lease, NonTransactional, Partition, PostgresReactor,
+        ReactionStep, Transactional, TransactionalReaction, WakeStrategy, DEFAULT_BATCH_SIZE,
+    },
+};
+use cqrs_core::{reactor::Reaction, EventUpcasters};
+use parking_lot::Mutex;
+use r2d2::Pool;
+use r2d2_postgres::PostgresConnectionManager;
+use std::{
+    collections::HashMap,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+    thread,
+};
+
+/// The status of a reaction registered with a [ReactionSupervisor].
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum ReactionStatus {
+    /// The reaction has been registered, but has not been started.
+    Idle,
+
+    /// The reaction is running on its own worker thread.
+    Running,
+
+    /// The reaction was stopped after reacting to the given number of events.
+    Stopped {
+        /// The number of events reacted to before the reaction was stopped.
+        event_count: usize,
+    },
+
+    /// The reaction stopped because of an error.
+    Failed {
+        /// A description of the error.
+        error: String,
+    },
+}
+
+impl fmt::Display for ReactionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReactionStatus::Idle => f.write_str("idle"),
+            ReactionStatus::Running => f.write_str("running"),
+            ReactionStatus::Stopped { event_count } => {
+                write!(f, "stopped after {} events", event_count)
+            }
+            ReactionStatus::Failed { ref error } => write!(f, "failed: {}", error),
+        }
+    }
+}
+
+/// An error while managing the reactions of a [ReactionSupervisor].
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum SupervisorError {
+    /// A reaction with the same name has already been registered.
+    AlreadyRegistered(&'static str),
+
+    /// No reaction with the given name has been registered.
+    NotRegistered(String),
+
+    /// The reaction is already running.
+    AlreadyRunning(&'static str),
+}
+
+impl fmt::Display for SupervisorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SupervisorError::AlreadyRegistered(name) => {
+                write!(f, "reaction already registered: {}", name)
+            }
+            SupervisorError::NotRegistered(ref name) => {
+                write!(f, "reaction not registered: {}", name)
+            }
+            SupervisorError::AlreadyRunning(name) => {
+                write!(f, "reaction already running: {}", name)
+            }
+        }
+    }
+}
+
+type Spawn<P> = Box<
+    dyn Fn(Arc<PostgresReactor<P>>, Arc<Mutex<ReactionStatus>>) -> thread::JoinHandle<()> + Send,
+>;
+
+type Running<P> = (Arc<PostgresReactor<P>>, thread::JoinHandle<()>);
+
+struct Worker<P> {
+    name: &'static str,
+    spawn: Spawn<P>,
+    status: Arc<Mutex<ReactionStatus>>,
+    running: Option<Running<P>>,
+}
+
+/// Runs many reactions concurrently, each on its own worker thread and with its own checkpoint.
+///
+/// Reactions are registered with a constructor, so that a stopped reaction can be started again from its last
+/// checkpoint with fresh state. Dropping the supervisor stops every running reaction and waits for its worker thread
+/// to finish its current batch.
+pub struct ReactionSupervisor<P = Pool<PostgresConnectionManager>> {
+    pool: P,
+    wake_strategy: WakeStrategy,
+    batch_size: u64,
+    error_policy: ErrorPolicy,
+    lease: Option<Lease>,
+    partition: Option<Partition>,
+    global_ordering: GlobalOrdering,
+    upcasters: Arc<EventUpcasters>,
+    workers: Mutex<HashMap<&'static str, Worker<P>>>,
+}
+
+impl<P> fmt::Debug for ReactionSupervisor<P>
+where
+    P: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let workers = self.workers.lock();
+        let mut names: Vec<_> = workers.keys().collect();
+        names.sort();
+
+        f.debug_struct("ReactionSupervisor")
+            .field("pool", &self.pool)
+            .field("wake_strategy", &self.wake_strategy)
+            .field("batch_size", &self.batch_size)
+            .field("error_policy", &self.error_policy)
+            .field("lease", &self.lease)
+            .field("partition", &self.partition)
+            .field("global_ordering", &self.global_ordering)
+            .field("upcasters", &self.upcasters)
+            .field("reactions", &names)
+            .finish()
+    }
+}
+
+impl<P> ReactionSupervisor<P>
+where
+    P: for<'conn> DbPool<'conn> + Clone + Send + Sync + 'static,
+{
+    /// Constructs a new supervisor with no registered reactions.
+    pub fn new(pool: P) -> Self {
+        ReactionSupervisor {
+            pool,
+            wake_strategy: WakeStrategy::default(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            error_policy: ErrorPolicy::default(),
+            lease: None,
+            partition: None,
+            global_ordering: GlobalOrdering::default(),
+            upcasters: Arc::default(),
+            workers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets how reactions wait for new events.
+    pub fn with_wake_strategy(mut self, wake_strategy: WakeStrategy) -> Self {
+        self.wake_strategy = wake_strategy;
+        self
+    }
+
+    /// Sets the maximum number of events read in a single batch by each reaction.
+    pub fn with_batch_size(mut self, batch_size: u64) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Sets what each reaction's reactor does when the reaction returns an error.
+    pub fn with_error_policy(mut self, error_policy: ErrorPolicy) -> Self {
+        self.error_policy = error_policy;
+        self
+    }
+
+    /// Only runs each reaction while holding a lease on it, so that the same reactions can safely be supervised on
+    /// many nodes.
+    pub fn with_lease(mut self, lease: Lease) -> Self {
+        self.lease = Some(lease);
+        self
+    }
+
+    /// Only reacts to events in the given partition of each reaction, so that the same reactions can be sharded across
+    /// many supervisors.
+    pub fn with_partition(mut self, partition: Partition) -> Self {
+        self.partition = Some(partition);
+        self
+    }
+
+    /// Sets the order in which each reaction's reactor reads events from the event store.
+    pub fn with_global_ordering(mut self, global_ordering: GlobalOrdering) -> Self {
+        self.global_ordering = global_ordering;
+        self
+    }
+
+    /// Sets the upcasters applied to event payloads persisted with older schema versions before they are fed to each
+    /// reaction.
+    pub fn with_upcasters(mut self, upcasters: impl Into<Arc<EventUpcasters>>) -> Self {
+        self.upcasters = upcasters.into();
+        self
+    }
+
+    /// Registers a reaction under its [Reaction::reaction_name], using `new_reaction` to construct it each time it is
+    /// started.
+    pub fn register<R, F>(&self, new_reaction: F) -> Result<(), SupervisorError>
+    where
+        R: Reaction + Send + 'static,
+        F: Fn() -> R + Send + 'static,
+    {
+        self.register_step(move || NonTransactional(new_reaction()))
+    }
+
+    /// Registers a transactional reaction under its [TransactionalReaction::reaction_name], using `new_reaction` to
+    /// construct it each time it is started.
+    pub fn register_transactional<R, F>(&self, new_reaction: F) -> Result<(), SupervisorError>
+    where
+        R: TransactionalReaction + Send + 'static,
+        F: Fn() -> R + Send + 'static,
+    {
+        self.register_step(move || Transactional(new_reaction()))
+    }
+
+    /// Registers a batch reaction under its [BatchReaction::reaction_name], using `new_reaction` to construct it each
+    /// time it is started.
+    pub fn register_batch<R, F>(&self, new_reaction: F) -> Result<(), SupervisorError>
+    where
+        R: BatchReaction + Send + 'static,
+        F: Fn() -> R + Send + 'static,
+    {
+        self.register_step(move || Batch(new_reaction()))
+    }
+
+    fn register_step<R, F>(&self, new_step: F) -> Result<(), SupervisorError>
+    where
+        R: ReactionStep + Send + 'static,
+        F: Fn() -> R + Send + 'static,
+    {
+        let name = R::reaction_name();
+        let mut workers = self.workers.lock();
+        if workers.contains_key(name) {
+            return Err(SupervisorError::AlreadyRegistered(name));
+        }
+
+        let spawn: Spawn<P> = Box::new(move |reactor, status| {
+            let step = new_step();
+            let started = reactor.stop_count();
+            thread::spawn(move || {
+                // Catch a panic in the reaction, so that its status does not stay running.
+                let result =
+                    panic::catch_unwind(AssertUnwindSafe(|| reactor.run_reaction(step, started)));
+                let mut status = status.lock();
+                *status = match result {
+                    Ok(Ok(event_count)) => ReactionStatus::Stopped { event_count },
+                    Ok(Err(err)) => {
+                        log::error!("reaction {}: {}", name, err);
+                        ReactionStatus::Failed {
+                            error: err.to_string(),
+                        }
+                    }
+                    Err(_) => {
+                        log::error!("reaction {}: panicked", name);
+                        ReactionStatus::Failed {
+                            error: String::from("reaction panicked"),
+                        }
+                    }
+                };
+            })
+        });
+
+        workers.insert(
+            name,
+            Worker {
+                name,
+                spawn,
+                status: Arc::new(Mutex::new(ReactionStatus::Idle)),
+                running: None,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Starts the named reaction on a new worker thread, from its last checkpoint.
+    pub fn start(&self, reaction_name: &str) -> Result<(), SupervisorError> {
+        let finished = {
+            let mut workers = self.workers.lock();
+            let worker = workers
+                .get_mut(reaction_name)
+                .ok_or_else(|| SupervisorError::NotRegistered(reaction_name.to_owned()))?;
+
+            self.start_worker(worker)?
+        };
+
+        if let Some(handle) = finished {
+            let _ = handle.join();
+        }
+
+        Ok(())
+    }
+
+    /// Signals the named reaction to stop, and waits for its worker thread to finish.
+    pub fn stop(&self, reaction_name: &str) -> Result<(), SupervisorError> {
+        let running = {
+            let mut workers = self.workers.lock();
+            let worker = workers
+                .get_mut(reaction_name)
+                .ok_or_else(|| SupervisorError::NotRegistered(reaction_name.to_owned()))?;
+
+            worker.running.take().map(|running| (worker.name, running))
+        };
+
+        stop_workers(running.into_iter().collect());
+
+        Ok(())
+    }
+
+    /// Starts all registered reactions that are not already running.
+    pub fn start_all(&self) {
+        let finished: Vec<_> = {
+            let mut workers = self.workers.lock();
+            workers
+                .values_mut()
+                .filter_map(|worker| self.start_worker(worker).ok())
+                .flatten()
+                .collect()
+        };
+
+        for handle in finished {
+            let _ = handle.join();
+        }
+    }
+
+    /// Signals all running reactions to stop, and waits for their worker threads to finish.
+    pub fn stop_all(&self) {
+        let running: Vec<_> = {
+            let mut workers = self.workers.lock();
+            workers
+                .values_mut()
+                .filter_map(|worker| worker.running.take().map(|running| (worker.name, running)))
+                .collect()
+        };
+
+        stop_workers(running);
+    }
+
+    /// Gets the status of the named reaction.
+    pub fn status(&self, reaction_name: &str) -> Option<ReactionStatus> {
+        let workers = self.workers.lock();
+        workers
+            .get(reaction_name)
+            .map(|worker| worker.status.lock().clone())
+    }
+
+    /// Gets the status of every registered reaction, ordered by reaction name.
+    pub fn statuses(&self) -> Vec<(&'static str, ReactionStatus)> {
+        let workers = self.workers.lock();
+        let mut statuses: Vec<_> = workers
+            .values()
+            .map(|worker| (worker.name, worker.status.lock().clone()))
+            .collect();
+        statuses.sort_by_key(|&(name, _)| name);
+        statuses
+    }
+
+    /// Starts the worker's reaction, returning the thread of its previous run, if any, which has finished reacting but
+    /// should be joined once the workers are no longer locked.
+    fn start_worker(
+        &self,
+        worker: &mut Worker<P>,
+    ) -> Result<Option<thread::JoinHandle<()>>, SupervisorError> {
+        if worker.running.is_some() && *worker.status.lock() == ReactionStatus::Running {
+            return Err(SupervisorError::AlreadyRunning(worker.name));
+        }
+
+        let finished = worker.running.take().map(|(_, handle)| handle);
+
+        let mut reactor = PostgresReactor::new(self.pool.clone())
+            .with_wake_strategy(self.wake_strategy)
+            .with_batch_size(self.batch_size)
+            .with_error_policy(self.error_policy)
+            .with_global_ordering(self.global_ordering)
+            .with_upcasters(Arc::clone(&self.upcasters));
+        if let Some(ref lease) = self.lease {
+            reactor = reactor.with_lease(lease.clone());
+        }
+        if let Some(partition) = self.partition {
+            reactor = reactor.with_partition(partition);
+        }
+        let reactor = Arc::new(reactor);
+
+        // Each run reports to its own status, so that a previous run which is still stopping cannot overwrite it.
+        worker.status = Arc::new(Mutex::new(ReactionStatus::Running));
+        let handle = (worker.spawn)(Arc::clone(&reactor), Arc::clone(&worker.status));
+        worker.running = Some((reactor, handle));
+
+        log::debug!("reaction {}: started", worker.name);
+
+        Ok(finished)
+    }
+}
+
+/// Signals the running reactions taken from their workers to stop, and waits for their worker threads to finish.
+fn stop_workers<P>(running: Vec<(&'static str, Running<P>)>) {
+    // Signal every reaction before waiting on any, so that they stop concurrently.
+    for &(_, (ref reactor, _)) in &running {
+        reactor.stop_reaction();
+    }
+
+    for (name, (_, handle)) in running {
+        let _ = handle.join();
+
+        log::debug!("reaction {}: stopped", name);
+    }
+}
+
+impl<P> Drop for ReactionSupervisor<P> {
+    fn drop(&mut self) {
+        let running: Vec<_> = self
+            .workers
+            .get_mut()
+            .values_mut()
+            .filter_map(|worker| worker.running.take().map(|running| (worker.name, running)))
+            .collect();
+
+        stop_workers(running);
+    }
+}
@@ -1,6 +1,6 @@
 use postgres::types::{FromSql, IsNull, ToSql, Type, BYTEA, INT8, JSON, JSONB};
 use serde::{de::DeserializeOwned, Serialize};
-use std::{error::Error, fmt};
+use std::{error::Error, fmt, time::Duration};
 
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub struct BorrowedJson<'a, T>(pub &'a T);
@@ -118,3 +118,8 @@ impl FromSql for Sequence {
         Ok(Sequence(event_number))
     }
 }
+
+/// Converts a duration to a (possibly fractional) number of seconds, for use as an `interval` parameter.
+pub fn as_seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1_000_000_000.0
+}
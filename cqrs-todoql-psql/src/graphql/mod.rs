@@ -1,23 +1,19 @@
 use std::{ops, sync::Arc};
 
+use crate::TodoStore;
 use juniper;
-use r2d2::Pool;
-use r2d2_postgres::PostgresConnectionManager;
 
 pub mod endpoint;
 mod schema;
 
 pub struct InnerContext {
-    pub backend: Pool<PostgresConnectionManager>,
+    pub store: TodoStore,
     pub id_provider: super::IdProvider,
 }
 
 impl InnerContext {
-    pub fn new(backend: Pool<PostgresConnectionManager>, id_provider: super::IdProvider) -> Self {
-        InnerContext {
-            backend,
-            id_provider,
-        }
+    pub fn new(store: TodoStore, id_provider: super::IdProvider) -> Self {
+        InnerContext { store, id_provider }
     }
 }
 
@@ -1,5 +1,4 @@
 use super::Context;
-use crate::TodoStore;
 use base64;
 use chrono::{DateTime, Utc};
 use cqrs::{
@@ -23,10 +22,9 @@ graphql_object!(Query: Context |&self| {
     field allTodos(&executor, first: Option<i32>, after: Option<Cursor>) -> FieldResult<TodoPage> {
         let context = executor.context();
 
-        let conn = context.backend.get()?;
-        let store = TodoStore::new(&*conn);
+        let store = &context.store;
 
-        let total_count = store.get_entity_count()?;
+        let total_count = store.with_store(|s| s.get_entity_count())?;
 
         const DEFAULT_LIMIT: u32 = 100;
         const MAX_LIMIT: u32 = 1000;
@@ -49,7 +47,7 @@ graphql_object!(Query: Context |&self| {
         };
 
         let entity_ids: Vec<_> =
-            store.get_entity_ids(offset, limit)?
+            store.with_store(|s| s.get_entity_ids(offset, limit))?
                 .into_iter()
                 .enumerate()
                 .map(|(i, id)| TodoEdge {
@@ -69,8 +67,7 @@ graphql_object!(Query: Context |&self| {
     field todo(&executor, id: ID) -> FieldResult<Option<TodoQL>> {
         let context = executor.context();
 
-        let conn = context.backend.get()?;
-        let store = TodoStore::new(&*conn);
+        let store = &context.store;
 
         let id = TodoId(id.to_string());
 
@@ -112,8 +109,7 @@ graphql_object!(TodoQL: Context |&self| {
     ) -> FieldResult<Vec<VersionedTodoEventQL>>
     {
         const MAX_PAGE_SIZE: u64 = 1_000;
-        let conn = executor.context().backend.get()?;
-        let store = TodoStore::new(&*conn);
+        let store = &executor.context().store;
 
         let before = if let Some(b) = before {
             let event_number = EventNumber::new(b.to_u64().ok_or("Invalid before version; must be a positive number")?).unwrap_or(EventNumber::MIN_VALUE);
@@ -125,7 +121,7 @@ graphql_object!(TodoQL: Context |&self| {
         let max_count = MAX_PAGE_SIZE.min(max_count.to_u64().ok_or("Invalid max_count; must be a positive integer")?);
 
         let events: Vec<VersionedTodoEventQL> =
-            store.read_events_reverse_with_metadata(self.0.id(), before, Some(max_count))?
+            store.with_store(|s| s.read_events_reverse_with_metadata(self.0.id(), before, Some(max_count)))?
                 .unwrap_or_default()
                 .into_iter()
                 .map(|r| r.map(VersionedTodoEventQL))
@@ -169,8 +165,7 @@ graphql_object!(TodoEdge: Context |&self| {
 
         let id = TodoId(self.agg_id.to_string());
 
-        let conn = context.backend.get()?;
-        let store = TodoStore::new(&*conn);
+        let store = &context.store;
 
         let entity = store.rehydrate(&id)?
             .map(|agg| TodoQL(Entity::new(id, agg)));
@@ -344,8 +339,7 @@ graphql_object!(Mutations: Context |&self| {
 
         let new_id = context.id_provider.new_id();
 
-        let conn = context.backend.get()?;
-        let store = TodoStore::new(&*conn);
+        let store = &context.store;
 
         let metadata = TodoMetadata {
             initiated_by: String::from("graphql"),
@@ -385,8 +379,7 @@ graphql_object!(TodoMutQL: Context |&self| {
 
         let id = TodoId(self.0.to_string());
 
-        let conn = context.backend.get()?;
-        let store = TodoStore::new(&*conn);
+        let store = &context.store;
 
         let metadata = TodoMetadata {
             initiated_by: String::from("graphql"),
@@ -413,8 +406,7 @@ graphql_object!(TodoMutQL: Context |&self| {
 
         let id = TodoId(self.0.to_string());
 
-        let conn = context.backend.get()?;
-        let store = TodoStore::new(&*conn);
+        let store = &context.store;
 
         let metadata = TodoMetadata {
             initiated_by: String::from("graphql"),
@@ -439,8 +431,7 @@ graphql_object!(TodoMutQL: Context |&self| {
 
         let id = TodoId(self.0.to_string());
 
-        let conn = context.backend.get()?;
-        let store = TodoStore::new(&*conn);
+        let store = &context.store;
 
         let metadata = TodoMetadata {
             initiated_by: String::from("graphql"),
@@ -465,8 +456,7 @@ graphql_object!(TodoMutQL: Context |&self| {
 
         let id = TodoId(self.0.to_string());
 
-        let conn = context.backend.get()?;
-        let store = TodoStore::new(&*conn);
+        let store = &context.store;
 
         let metadata = TodoMetadata {
             initiated_by: String::from("graphql"),
@@ -491,8 +481,7 @@ graphql_object!(TodoMutQL: Context |&self| {
 
         let id = TodoId(self.0.to_string());
 
-        let conn = context.backend.get()?;
-        let store = TodoStore::new(&*conn);
+        let store = &context.store;
 
         let metadata = TodoMetadata {
             initiated_by: String::from("graphql"),
@@ -517,8 +506,7 @@ graphql_object!(TodoMutQL: Context |&self| {
 
         let id = TodoId(self.0.to_string());
 
-        let conn = context.backend.get()?;
-        let store = TodoStore::new(&*conn);
+        let store = &context.store;
 
         let metadata = TodoMetadata {
             initiated_by: String::from("graphql"),
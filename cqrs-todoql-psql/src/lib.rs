@@ -31,8 +31,7 @@ impl cqrs::SnapshotStrategy for SnapshotEvery10 {
     }
 }
 
-type TodoStore<'conn> = cqrs_postgres::PostgresStore<
-    'conn,
+type TodoStore = cqrs_postgres::PooledPostgresStore<
     cqrs_todo_core::TodoAggregate,
     cqrs_todo_core::TodoEvent,
     cqrs_todo_core::TodoMetadata,
@@ -64,7 +63,7 @@ pub fn start_todo_server(conn_str: &str, prefill_qty: usize) -> iron::Listening
         }
     }
 
-    let context = graphql::InnerContext::new(pool, id_provider);
+    let context = graphql::InnerContext::new(TodoStore::new(pool), id_provider);
 
     let chain = graphql::endpoint::create_chain(context);
 
@@ -0,0 +1,406 @@
+//! Asynchronous counterparts to the [EntitySource](crate::EntitySource), [EntitySink](crate::EntitySink), and
+//! [EntityStore](crate::EntityStore) helpers.
+//!
+//! Any synchronous store can be used here, as every synchronous store is also an asynchronous store (see
+//! [async_store](cqrs_core::async_store)).
+//!
+//! _Requires the `async` feature._
+
+use crate::entity::{
+    EntityError, EntityExecAndPersistError, EntityLoadError, EntityPersistError, HydratedAggregate,
+};
+use cqrs_core::{
+    async_store::{AsyncEventSink, AsyncEventSource, AsyncSnapshotSink, AsyncSnapshotSource},
+    Aggregate, AggregateCommand, AggregateEvent, AggregateId, Events, Precondition, ProducedEvent,
+    Version,
+};
+use std::future::Future;
+
+/// The result of loading an entity from a snapshot.
+pub type AsyncEntityLoadSnapshotResult<A, L> =
+    Result<Option<HydratedAggregate<A>>, <L as AsyncSnapshotSource<A>>::Error>;
+
+/// The result of refreshing an entity.
+pub type AsyncEntityRefreshResult<A, E, L> = Result<
+    Option<HydratedAggregate<A>>,
+    EntityLoadError<<L as AsyncEventSource<A, E>>::Error, <L as AsyncSnapshotSource<A>>::Error>,
+>;
+
+/// The result of persisting an entity.
+pub type AsyncEntityPersistResult<A, E, M, L> = Result<
+    (),
+    EntityPersistError<<L as AsyncEventSink<A, E, M>>::Error, <L as AsyncSnapshotSink<A>>::Error>,
+>;
+
+/// The result of executing a command against an entity, after attempting to persist any
+/// new events and possibly updating the snapshot.
+pub type AsyncEntityExecAndPersistResult<A, C, M, L> = Result<
+    HydratedAggregate<A>,
+    EntityExecAndPersistError<
+        A,
+        C,
+        <L as AsyncEventSink<A, ProducedEvent<A, C>, M>>::Error,
+        <L as AsyncSnapshotSink<A>>::Error,
+    >,
+>;
+
+/// The result of loading an entity, then executing a command and attempting to persist
+/// any new events and possibly updating the snapshot.
+pub type AsyncEntityResult<A, C, M, L> = Result<
+    HydratedAggregate<A>,
+    EntityError<
+        <L as AsyncEventSource<A, ProducedEvent<A, C>>>::Error,
+        <L as AsyncSnapshotSource<A>>::Error,
+        A,
+        C,
+        <L as AsyncEventSink<A, ProducedEvent<A, C>, M>>::Error,
+        <L as AsyncSnapshotSink<A>>::Error,
+    >,
+>;
+
+/// The result of trying to load an entity, which may not exists, then executing a command and
+/// attempting to persist any new events and possibly updating the snapshot
+pub type AsyncEntityOptionResult<A, C, M, L> = Result<
+    Option<HydratedAggregate<A>>,
+    EntityError<
+        <L as AsyncEventSource<A, ProducedEvent<A, C>>>::Error,
+        <L as AsyncSnapshotSource<A>>::Error,
+        A,
+        C,
+        <L as AsyncEventSink<A, ProducedEvent<A, C>, M>>::Error,
+        <L as AsyncSnapshotSink<A>>::Error,
+    >,
+>;
+
+/// An asynchronous source for loading an entity.
+pub trait AsyncEntitySource<A, E>: AsyncEventSource<A, E> + AsyncSnapshotSource<A> + Sync
+where
+    A: Aggregate + Send + Sync,
+    E: AggregateEvent<A>,
+{
+    /// Loads an identified entity from the latest known snapshot.
+    ///
+    /// If the `AsyncSnapshotSource` returns an error, it is passed along. If the source does not have a snapshot
+    /// for the requested entity, returns `Ok(None)`.
+    fn load_from_snapshot<I>(
+        &self,
+        id: &I,
+    ) -> impl Future<Output = AsyncEntityLoadSnapshotResult<A, Self>> + Send
+    where
+        I: AggregateId<A> + Sync,
+    {
+        async move {
+            let entity = self.get_snapshot(id).await?.map(HydratedAggregate::from);
+
+            Ok(entity)
+        }
+    }
+
+    /// Refreshes an existing hydrated aggregate with the given id.
+    ///
+    /// Errors may occur while loading the events.
+    fn refresh<I>(
+        &self,
+        id: &I,
+        aggregate: &mut HydratedAggregate<A>,
+    ) -> impl Future<Output = Result<(), <Self as AsyncEventSource<A, E>>::Error>> + Send
+    where
+        I: AggregateId<A> + Sync,
+    {
+        async move {
+            let seq_events = self
+                .read_events(id, aggregate.version().into(), None)
+                .await?;
+
+            if let Some(seq_events) = seq_events {
+                for seq_event in seq_events {
+                    aggregate.apply(seq_event.event);
+
+                    debug_assert_eq!(Version::Number(seq_event.sequence), aggregate.version());
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Loads an entity from the most recent snapshot of its aggregate, then applies any newer events that have not yet been
+    /// applied.
+    ///
+    /// Errors may occur while loading the snapshot or the events. If no snapshot or events can be found
+    /// for the entity, returns `Ok(None)`
+    fn rehydrate<I>(
+        &self,
+        id: &I,
+    ) -> impl Future<Output = AsyncEntityRefreshResult<A, E, Self>> + Send
+    where
+        I: AggregateId<A> + Sync,
+    {
+        async move {
+            let aggregate = self
+                .load_from_snapshot(id)
+                .await
+                .map_err(EntityLoadError::SnapshotSource)?;
+
+            let missing = aggregate.is_none();
+
+            let mut aggregate = aggregate.unwrap_or_default();
+
+            self.refresh(id, &mut aggregate)
+                .await
+                .map_err(EntityLoadError::EventSource)?;
+
+            if missing && aggregate.version() == Version::Initial {
+                Ok(None)
+            } else {
+                Ok(Some(aggregate))
+            }
+        }
+    }
+}
+
+impl<A, E, T> AsyncEntitySource<A, E> for T
+where
+    A: Aggregate + Send + Sync,
+    E: AggregateEvent<A>,
+    T: AsyncEventSource<A, E> + AsyncSnapshotSource<A> + Sync,
+{
+}
+
+/// An asynchronous sink for persisting an entity.
+pub trait AsyncEntitySink<A, E, M>: AsyncEventSink<A, E, M> + AsyncSnapshotSink<A> + Sync
+where
+    A: Aggregate + Send + Sync,
+    E: AggregateEvent<A> + Send,
+    M: Send,
+{
+    /// Attempts persist a sequence of events to an identified aggregate and then apply those
+    /// events to the mutable aggregate. Then attempts to persist a snapshot of the aggregate
+    /// if the sink's snapshot strategy recommends it. Returns the resulting aggregate if persistence was successful.
+    ///
+    /// Errors may occur while persisting the events or the snapshot or the events. If there result indicates
+    /// an error while persisting the snapshot, then any events have already been safely persisted.
+    fn apply_events_and_persist<I, Es>(
+        &self,
+        id: &I,
+        aggregate: &mut HydratedAggregate<A>,
+        events: Es,
+        expected_version: Version,
+        metadata: M,
+    ) -> impl Future<Output = AsyncEntityPersistResult<A, E, M, Self>> + Send
+    where
+        I: AggregateId<A> + Sync,
+        Es: Events<E> + Send,
+    {
+        async move {
+            self.append_events(
+                id,
+                events.as_ref(),
+                Some(Precondition::ExpectedVersion(expected_version)),
+                metadata,
+            )
+            .await
+            .map_err(EntityPersistError::EventSink)?;
+
+            for event in events {
+                aggregate.apply(event);
+            }
+
+            let new_snapshot_version = self
+                .persist_snapshot(
+                    id,
+                    aggregate.state(),
+                    aggregate.version(),
+                    aggregate.snapshot_version(),
+                )
+                .await
+                .map_err(EntityPersistError::SnapshotSink)?;
+            aggregate.set_snapshot_version(new_snapshot_version);
+
+            Ok(())
+        }
+    }
+
+    /// Executes a command against an aggregate, using the default if `None`. If successful, then persists any resulting
+    /// events (and possibly updating the snapshot, see [AsyncEntitySink::apply_events_and_persist]). Returns the
+    /// resulting aggregate if all persistence operations were successful.
+    fn exec_and_persist<I, C>(
+        &self,
+        id: &I,
+        aggregate: Option<HydratedAggregate<A>>,
+        command: C,
+        precondition: Option<Precondition>,
+        metadata: M,
+    ) -> impl Future<Output = AsyncEntityExecAndPersistResult<A, C, M, Self>> + Send
+    where
+        I: AggregateId<A> + Sync,
+        C: AggregateCommand<A, Event = E> + Send,
+        C::Events: Events<E> + Send,
+    {
+        async move {
+            if let Some(precondition) = precondition {
+                let initial_version = aggregate.as_ref().map(HydratedAggregate::version);
+                precondition.verify(initial_version)?;
+            }
+
+            let mut aggregate = aggregate.unwrap_or_default();
+
+            let expected_version = aggregate.version();
+
+            match aggregate.state().execute(command) {
+                Ok(events) => {
+                    self.apply_events_and_persist(
+                        id,
+                        &mut aggregate,
+                        events,
+                        expected_version,
+                        metadata,
+                    )
+                    .await
+                    .map_err(EntityExecAndPersistError::Persist)?;
+                }
+                Err(e) => {
+                    return Err(EntityExecAndPersistError::Exec(aggregate, e));
+                }
+            }
+
+            Ok(aggregate)
+        }
+    }
+}
+
+impl<A, E, M, T> AsyncEntitySink<A, E, M> for T
+where
+    A: Aggregate + Send + Sync,
+    E: AggregateEvent<A> + Send,
+    M: Send,
+    T: AsyncEventSink<A, E, M> + AsyncSnapshotSink<A> + Sync,
+{
+}
+
+/// A generalized asynchronous entity store that can perform operations on its entities.
+pub trait AsyncEntityStore<A, E, M>: AsyncEntitySource<A, E> + AsyncEntitySink<A, E, M>
+where
+    A: Aggregate + Send + Sync,
+    E: AggregateEvent<A> + Send,
+    M: Send,
+{
+    /// Attempts to load an aggregate, using the default instance if the aggregate does not yet exist, executes a
+    /// command and persists any new events, possibly peristing a new snapshot if necessary.
+    fn load_or_default_exec_and_persist<I, C>(
+        &self,
+        id: &I,
+        command: C,
+        precondition: Option<Precondition>,
+        metadata: M,
+    ) -> impl Future<Output = AsyncEntityResult<A, C, M, Self>> + Send
+    where
+        I: AggregateId<A> + Sync,
+        C: AggregateCommand<A, Event = E> + Send,
+        C::Events: Events<E> + Send,
+    {
+        async move {
+            let aggregate = self.rehydrate(id).await.map_err(EntityError::Load)?;
+            let aggregate = self
+                .exec_and_persist(id, aggregate, command, precondition, metadata)
+                .await?;
+
+            Ok(aggregate)
+        }
+    }
+
+    /// Loads an aggregate, executes a command and persists any new events, possibly persisting
+    /// a new snapshot if necessary.
+    ///
+    /// If the aggregate does not exist, returns `Ok(None)`.
+    fn load_exec_and_persist<I, C>(
+        &self,
+        id: &I,
+        command: C,
+        precondition: Option<Precondition>,
+        metadata: M,
+    ) -> impl Future<Output = AsyncEntityOptionResult<A, C, M, Self>> + Send
+    where
+        I: AggregateId<A> + Sync,
+        C: AggregateCommand<A, Event = E> + Send,
+        C::Events: Events<E> + Send,
+    {
+        async move {
+            if let Some(aggregate) = self.rehydrate(id).await.map_err(EntityError::Load)? {
+                let aggregate = self
+                    .exec_and_persist(id, Some(aggregate), command, precondition, metadata)
+                    .await?;
+
+                Ok(Some(aggregate))
+            } else {
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl<A, E, M, T> AsyncEntityStore<A, E, M> for T
+where
+    A: Aggregate + Send + Sync,
+    E: AggregateEvent<A> + Send,
+    M: Send,
+    T: AsyncEntitySource<A, E> + AsyncEntitySink<A, E, M>,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        memory::{EventStore, StateStore},
+        testing::*,
+        CompositeEntitySink, CompositeEntitySource, CompositeEntityStore,
+    };
+
+    type TestEventStore = EventStore<TestAggregate, TestEvent, TestMetadata>;
+
+    #[test]
+    fn rehydrates_missing_entity_as_none() {
+        let events = TestEventStore::default();
+        let snapshots = StateStore::<TestAggregate>::default();
+        let source = CompositeEntitySource::default()
+            .with_event_source(&events)
+            .with_snapshot_source(&snapshots);
+
+        let aggregate = block_on(source.rehydrate(&TestId("missing"))).unwrap();
+
+        assert_eq!(None, aggregate);
+    }
+
+    #[test]
+    fn can_exec_and_persist_through_sync_stores() {
+        let events = TestEventStore::default();
+        let snapshots = StateStore::<TestAggregate>::default();
+        let source = CompositeEntitySource::default()
+            .with_event_source(&events)
+            .with_snapshot_source(&snapshots);
+        let sink: CompositeEntitySink<
+            TestAggregate,
+            TestEvent,
+            TestMetadata,
+            TestEventStore,
+            StateStore<TestAggregate>,
+        > = CompositeEntitySink::default()
+            .with_event_sink(&events)
+            .with_snapshot_sink(&snapshots);
+        let store = CompositeEntityStore::default()
+            .with_entity_source(source)
+            .with_entity_sink(sink);
+
+        let aggregate = block_on(store.load_or_default_exec_and_persist(
+            &TestId("test"),
+            TestCommand,
+            Some(Precondition::New),
+            TestMetadata,
+        ))
+        .unwrap();
+
+        assert_eq!(Version::Initial, aggregate.version());
+        assert_eq!(Some(Version::Initial), aggregate.snapshot_version());
+    }
+}
@@ -1,8 +1,8 @@
 use crate::trivial::{NullEventStore, NullSnapshotStore};
 use cqrs_core::{
-    Aggregate, AggregateCommand, AggregateEvent, AggregateId, CqrsError, EventNumber, EventSink,
-    EventSource, Events, Precondition, ProducedEvent, Since, SnapshotSink, SnapshotSource, Version,
-    VersionedAggregate,
+    Aggregate, AggregateCommand, AggregateEvent, AggregateId, Before, CqrsError, EventNumber,
+    EventSink, EventSource, Events, Precondition, ProducedEvent, ReverseEventSource, Since,
+    SnapshotSink, SnapshotSource, Version, VersionedAggregate,
 };
 use std::{
     borrow::{Borrow, BorrowMut},
@@ -59,6 +59,19 @@ where
     }
 }
 
+impl<A> From<VersionedAggregate<A>> for HydratedAggregate<A>
+where
+    A: Aggregate,
+{
+    fn from(snapshot: VersionedAggregate<A>) -> Self {
+        HydratedAggregate {
+            version: snapshot.version,
+            snapshot_version: Some(snapshot.version),
+            state: snapshot.payload,
+        }
+    }
+}
+
 impl<A> AsRef<A> for HydratedAggregate<A>
 where
     A: Aggregate,
@@ -188,15 +201,7 @@ where
     where
         I: AggregateId<A>,
     {
-        let entity = if let Some(snapshot) = self.get_snapshot(id)? {
-            Some(HydratedAggregate {
-                version: snapshot.version,
-                snapshot_version: Some(snapshot.version),
-                state: snapshot.payload,
-            })
-        } else {
-            None
-        };
+        let entity = self.get_snapshot(id)?.map(HydratedAggregate::from);
 
         Ok(entity)
     }
@@ -570,6 +575,26 @@ where
     }
 }
 
+impl<'e, 's, A, E, ES, SS> ReverseEventSource<A, E> for CompositeEntitySource<'e, 's, A, E, ES, SS>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+    ES: ReverseEventSource<A, E> + 'e,
+    SS: SnapshotSource<A> + 's,
+{
+    fn read_events_reverse<I>(
+        &self,
+        id: &I,
+        before: Before,
+        max_count: Option<u64>,
+    ) -> Result<Option<Self::Events>, Self::Error>
+    where
+        I: AggregateId<A>,
+    {
+        self.event_source.read_events_reverse(id, before, max_count)
+    }
+}
+
 impl<'e, 's, A, E, ES, SS> SnapshotSource<A> for CompositeEntitySource<'e, 's, A, E, ES, SS>
 where
     A: Aggregate,
@@ -718,7 +743,7 @@ where
 {
     entity_source: ES,
     entity_sink: SS,
-    _phantom: PhantomData<*const (A, E, M)>,
+    _phantom: PhantomData<fn(A, E, M)>,
 }
 
 impl<A, E, M> Default
@@ -803,6 +828,27 @@ where
     }
 }
 
+impl<A, E, M, ES, SS> ReverseEventSource<A, E> for CompositeEntityStore<A, E, M, ES, SS>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+    ES: EntitySource<A, E> + ReverseEventSource<A, E>,
+    SS: EntitySink<A, E, M>,
+{
+    fn read_events_reverse<I>(
+        &self,
+        id: &I,
+        before: Before,
+        max_count: Option<u64>,
+    ) -> Result<Option<Self::Events>, Self::Error>
+    where
+        I: AggregateId<A>,
+    {
+        self.entity_source
+            .read_events_reverse(id, before, max_count)
+    }
+}
+
 impl<A, E, M, ES, SS> SnapshotSource<A> for CompositeEntityStore<A, E, M, ES, SS>
 where
     A: Aggregate,
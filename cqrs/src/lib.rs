@@ -41,6 +41,9 @@
 pub mod memory;
 pub mod trivial;
 
+#[cfg(feature = "async")]
+pub mod async_entity;
+
 mod entity;
 
 #[cfg(test)]
@@ -1,10 +1,16 @@
 //! A basic, in-memory event stream.
 
 use cqrs_core::{
-    Aggregate, AggregateEvent, AggregateId, EventNumber, EventSink, EventSource, Precondition,
-    Since, SnapshotSink, SnapshotSource, Version, VersionedAggregate, VersionedEvent,
+    reactor::{
+        MetadataPredicatesUnsupported, RawEventPredicate, Reaction, ReactionError,
+        ReactionPredicate, Reactor,
+    },
+    Aggregate, AggregateEvent, AggregateId, Before, EntityPreconditionFailed, EventNumber,
+    EventSink, EventSource, EventWithMetadataSource, GlobalEventSource, Precondition, RawEvent,
+    ReverseEventSource, SerializableEvent, Since, SnapshotSink, SnapshotSource, UnitOfWork,
+    UnitOfWorkSink, Version, VersionedAggregate, VersionedEvent, VersionedEventWithMetadata,
 };
-use parking_lot::{RwLock, RwLockUpgradableReadGuard};
+use parking_lot::{Condvar, Mutex, RwLock, RwLockUpgradableReadGuard};
 use std::{
     collections::{hash_map::RandomState, HashMap},
     fmt,
@@ -12,6 +18,7 @@ use std::{
     iter,
     marker::PhantomData,
     sync::Arc,
+    time::{Duration, Instant},
 };
 use void::Void;
 
@@ -21,8 +28,15 @@ struct EventStream<Event, Metadata> {
     metadata: Vec<Arc<Metadata>>,
 }
 
+#[derive(Debug)]
+struct GlobalLogEntry<Event> {
+    entity_id: Arc<str>,
+    event: Event,
+}
+
 type LockedHashMap<K, V, H> = RwLock<HashMap<K, V, H>>;
 type LockedEventStream<E, M> = RwLock<EventStream<VersionedEvent<E>, M>>;
+type LockedGlobalLog<E> = RwLock<Vec<GlobalLogEntry<VersionedEvent<E>>>>;
 
 /// An in-memory event store
 #[derive(Debug)]
@@ -33,7 +47,8 @@ where
     Hasher: BuildHasher,
 {
     inner: LockedHashMap<String, LockedEventStream<E, M>, Hasher>,
-    _phantom: PhantomData<*const A>,
+    log: LockedGlobalLog<E>,
+    _phantom: PhantomData<fn(A)>,
 }
 
 impl<A, E, M, Hasher> Default for EventStore<A, E, M, Hasher>
@@ -45,6 +60,7 @@ where
     fn default() -> Self {
         EventStore {
             inner: RwLock::new(HashMap::default()),
+            log: RwLock::default(),
             _phantom: PhantomData,
         }
     }
@@ -60,6 +76,7 @@ where
     pub fn with_hasher(hasher: Hasher) -> Self {
         EventStore {
             inner: RwLock::new(HashMap::with_hasher(hasher)),
+            log: RwLock::default(),
             _phantom: PhantomData,
         }
     }
@@ -119,6 +136,102 @@ where
     }
 }
 
+impl<A, E, M, Hasher> ReverseEventSource<A, E> for EventStore<A, E, M, Hasher>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + Clone,
+    Hasher: BuildHasher,
+{
+    fn read_events_reverse<I>(
+        &self,
+        id: &I,
+        before: Before,
+        max_count: Option<u64>,
+    ) -> Result<Option<Self::Events>, Self::Error>
+    where
+        I: AggregateId<A>,
+    {
+        let table = self.inner.read();
+
+        let stream = table.get(id.as_str());
+
+        let result = stream.map(|stream| {
+            let stream = stream.read();
+
+            let end = match before {
+                Before::EndOfStream => stream.events.len(),
+                Before::Event(event_number) => {
+                    (event_number.get() as usize - 1).min(stream.events.len())
+                }
+            };
+            let take = max_count.map_or(usize::max_value(), |max_count| {
+                max_count.min(usize::max_value() as u64) as usize
+            });
+
+            stream.events[..end]
+                .iter()
+                .rev()
+                .take(take)
+                .map(ToOwned::to_owned)
+                .collect()
+        });
+
+        Ok(result)
+    }
+}
+
+impl<A, E, M, Hasher> EventWithMetadataSource<A, E, M> for EventStore<A, E, M, Hasher>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + Clone,
+    M: Clone,
+    Hasher: BuildHasher,
+{
+    type Error = Void;
+    type Events = Vec<VersionedEventWithMetadata<E, M>>;
+
+    fn read_events_with_metadata<I>(
+        &self,
+        id: &I,
+        since: Since,
+        max_count: Option<u64>,
+    ) -> Result<Option<Self::Events>, Self::Error>
+    where
+        I: AggregateId<A>,
+    {
+        let table = self.inner.read();
+
+        let stream = table.get(id.as_str());
+
+        let result = stream.map(|stream| {
+            let stream = stream.read();
+
+            let skip = match since {
+                Since::BeginningOfStream => 0,
+                Since::Event(event_number) => event_number.get() as usize,
+            };
+            let take = max_count.map_or(usize::max_value(), |max_count| {
+                max_count.min(usize::max_value() as u64) as usize
+            });
+
+            stream
+                .events
+                .iter()
+                .zip(&stream.metadata)
+                .skip(skip)
+                .take(take)
+                .map(|(event, metadata)| VersionedEventWithMetadata {
+                    sequence: event.sequence,
+                    event: event.event.to_owned(),
+                    metadata: M::clone(metadata),
+                })
+                .collect()
+        });
+
+        Ok(result)
+    }
+}
+
 /// An error indicating that a precondition has failed.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct PreconditionFailed(pub Precondition);
@@ -159,28 +272,16 @@ where
             let table = RwLockUpgradableReadGuard::downgrade(table);
             let stream = table.get(id.as_str()).unwrap().upgradable_read();
 
-            let mut sequence = Version::new(stream.events.len() as u64).next_event();
-            let first_sequence = sequence;
+            let current_version = Version::new(stream.events.len() as u64);
 
             if let Some(precondition) = precondition {
-                precondition.verify(Some(first_sequence.into()))?;
+                precondition.verify(Some(current_version))?;
             }
 
             let stream = &mut RwLockUpgradableReadGuard::upgrade(stream);
 
-            let metadata = Arc::new(metadata);
-            stream
-                .metadata
-                .extend(iter::repeat(metadata).take(events.len()));
-
-            stream.events.extend(events.iter().map(|event| {
-                let versioned_event = VersionedEvent {
-                    sequence,
-                    event: event.to_owned(),
-                };
-                sequence.incr();
-                versioned_event
-            }));
+            let first_sequence = current_version.next_event();
+            self.push_events(id.as_str().into(), stream, first_sequence, events, metadata);
 
             Ok(first_sequence)
         } else {
@@ -188,33 +289,362 @@ where
                 precondition.verify(None)?;
             }
 
-            let mut sequence = EventNumber::MIN_VALUE;
+            let mut table = RwLockUpgradableReadGuard::upgrade(table);
+            let stream = table.entry(id.as_str().into()).or_insert_with(|| {
+                RwLock::new(EventStream {
+                    events: Vec::new(),
+                    metadata: Vec::new(),
+                })
+            });
+
+            self.push_events(
+                id.as_str().into(),
+                stream.get_mut(),
+                EventNumber::MIN_VALUE,
+                events,
+                metadata,
+            );
+
+            Ok(EventNumber::MIN_VALUE)
+        }
+    }
+}
+
+impl<A, E, M, Hasher> UnitOfWorkSink<A, E, M> for EventStore<A, E, M, Hasher>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + Clone,
+    Hasher: BuildHasher,
+{
+    type Error = EntityPreconditionFailed;
+
+    fn commit(&self, unit_of_work: UnitOfWork<A, E, M>) -> Result<Vec<EventNumber>, Self::Error> {
+        let mut table = self.inner.write();
+
+        let appends = unit_of_work.into_appends();
+
+        let mut first_sequences = Vec::with_capacity(appends.len());
+        {
+            let mut staged_versions: HashMap<&str, Version> = HashMap::new();
+            for append in &appends {
+                let current_version = staged_versions
+                    .get(append.entity_id.as_str())
+                    .cloned()
+                    .or_else(|| {
+                        table
+                            .get(&append.entity_id)
+                            .map(|stream| Version::new(stream.read().events.len() as u64))
+                    });
+
+                if let Some(precondition) = append.precondition {
+                    precondition
+                        .verify(current_version)
+                        .map_err(|precondition| EntityPreconditionFailed {
+                            entity_id: append.entity_id.clone(),
+                            precondition,
+                        })?;
+                }
+
+                let current_version = current_version.unwrap_or_default();
+                first_sequences.push(current_version.next_event());
+                staged_versions.insert(
+                    &append.entity_id,
+                    Version::new(current_version.get() + append.events.len() as u64),
+                );
+            }
+        }
+
+        for (append, &first_sequence) in appends.into_iter().zip(&first_sequences) {
+            let entity_id: Arc<str> = append.entity_id.as_str().into();
+            let stream = table.entry(append.entity_id).or_insert_with(|| {
+                RwLock::new(EventStream {
+                    events: Vec::new(),
+                    metadata: Vec::new(),
+                })
+            });
+
+            self.push_events(
+                entity_id,
+                stream.get_mut(),
+                first_sequence,
+                &append.events,
+                append.metadata,
+            );
+        }
 
-            let metadata = Arc::new(metadata);
-            let metadata_stream = iter::repeat(metadata).take(events.len()).collect();
+        Ok(first_sequences)
+    }
+}
 
-            let new_stream = EventStream {
-                events: events
-                    .iter()
-                    .map(|event| {
-                        let versioned_event = VersionedEvent {
-                            sequence,
-                            event: event.to_owned(),
-                        };
-                        sequence.incr();
-                        versioned_event
-                    })
-                    .collect(),
-                metadata: metadata_stream,
+impl<A, E, M, Hasher> EventStore<A, E, M, Hasher>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + Clone,
+    Hasher: BuildHasher,
+{
+    /// Appends events to a stream that is already locked for writing, recording them in the global log.
+    ///
+    /// The global log lock is held while the stream is updated, so that global positions follow append order.
+    fn push_events(
+        &self,
+        entity_id: Arc<str>,
+        stream: &mut EventStream<VersionedEvent<E>, M>,
+        first_sequence: EventNumber,
+        events: &[E],
+        metadata: M,
+    ) {
+        let mut log = self.log.write();
+
+        let metadata = Arc::new(metadata);
+        stream
+            .metadata
+            .extend(iter::repeat(metadata).take(events.len()));
+
+        let mut sequence = first_sequence;
+        for event in events {
+            let versioned_event = VersionedEvent {
+                sequence,
+                event: event.to_owned(),
             };
+            sequence.incr();
 
-            let stream = RwLock::new(new_stream);
+            log.push(GlobalLogEntry {
+                entity_id: Arc::clone(&entity_id),
+                event: versioned_event.clone(),
+            });
+            stream.events.push(versioned_event);
+        }
+    }
+}
 
-            let mut table = RwLockUpgradableReadGuard::upgrade(table);
-            table.insert(id.as_str().into(), stream);
+impl<A, E, M, Hasher> GlobalEventSource for EventStore<A, E, M, Hasher>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + SerializableEvent + Clone,
+    Hasher: BuildHasher,
+{
+    type Error = <E as SerializableEvent>::Error;
+    type Events = Vec<RawEvent>;
 
-            Ok(EventNumber::MIN_VALUE)
+    fn read_all_events(
+        &self,
+        since: Since,
+        max_count: Option<u64>,
+    ) -> Result<Self::Events, Self::Error> {
+        let log = self.log.read();
+
+        let skip = match since {
+            Since::BeginningOfStream => 0,
+            Since::Event(event_id) => event_id.get() as usize,
+        };
+        let take = max_count.map_or(usize::max_value(), |max_count| {
+            max_count.min(usize::max_value() as u64) as usize
+        });
+
+        log.iter()
+            .enumerate()
+            .skip(skip)
+            .take(take)
+            .map(|(index, entry)| {
+                let mut payload = Vec::new();
+                entry.event.event.serialize_event_to_buffer(&mut payload)?;
+                Ok(RawEvent {
+                    event_id: EventNumber::new(index as u64 + 1).unwrap(),
+                    aggregate_type: A::aggregate_type().into(),
+                    entity_id: entry.entity_id.as_ref().into(),
+                    sequence: entry.event.sequence,
+                    event_type: entry.event.event.event_type().into(),
+                    payload_format: String::from("json"),
+                    payload,
+                })
+            })
+            .collect()
+    }
+}
+
+impl<A, E, M, Hasher> EventStore<A, E, M, Hasher>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + SerializableEvent + Clone,
+    Hasher: BuildHasher,
+{
+    /// Subscribes to the events matching `predicate`, starting from the beginning of the global log.
+    ///
+    /// Returns an error if `predicate` has any metadata predicates, which cannot be matched against the events in the
+    /// global log.
+    pub fn subscribe(
+        &self,
+        predicate: ReactionPredicate,
+    ) -> Result<Subscription<&Self>, MetadataPredicatesUnsupported> {
+        Subscription::new(self, predicate, Since::BeginningOfStream)
+    }
+}
+
+/// A subscription to the events from a [GlobalEventSource] which match a [ReactionPredicate].
+///
+/// Each call to [Subscription::poll] returns the matching events appended since the previous call, so tests can drive
+/// reactions and projections synchronously.
+#[derive(Debug)]
+pub struct Subscription<S> {
+    source: S,
+    predicate: RawEventPredicate,
+    since: Since,
+}
+
+impl<S> Subscription<S>
+where
+    S: GlobalEventSource,
+{
+    /// Subscribes to the events from `source` matching `predicate`, after the position given by `since`.
+    ///
+    /// Returns an error if `predicate` has any metadata predicates, which cannot be matched against the raw events read
+    /// from a [GlobalEventSource].
+    pub fn new(
+        source: S,
+        predicate: ReactionPredicate,
+        since: Since,
+    ) -> Result<Self, MetadataPredicatesUnsupported> {
+        Ok(Subscription {
+            source,
+            predicate: RawEventPredicate::new(predicate)?,
+            since,
+        })
+    }
+
+    /// Reads the matching events appended since the last poll, in global order.
+    pub fn poll(&mut self) -> Result<Vec<RawEvent>, S::Error> {
+        let events = self.source.read_all_events(self.since, None)?;
+
+        let mut matching = Vec::new();
+        for event in events {
+            self.since = Since::Event(event.event_id);
+            if self.predicate.matches(&event) {
+                matching.push(event);
+            }
         }
+
+        Ok(matching)
+    }
+
+    /// The position of the last event read by the subscription, whether or not it matched.
+    pub fn position(&self) -> Since {
+        self.since
+    }
+}
+
+/// An in-memory reactor, which feeds events from a [GlobalEventSource] such as [EventStore] to reactions.
+///
+/// The position of each reaction is kept in memory, so a reaction that is stopped and started again on the same
+/// reactor resumes after the last event it reacted to. [Reactor::stop_reaction] stops the reactions that are running
+/// at the time, and a reaction started afterwards runs until the reactor is stopped again.
+#[derive(Debug)]
+pub struct MemoryReactor<S> {
+    source: S,
+    stop_count: Mutex<u64>,
+    stop_signal: Condvar,
+    positions: Mutex<HashMap<&'static str, Since>>,
+}
+
+impl<S> MemoryReactor<S>
+where
+    S: GlobalEventSource,
+{
+    /// Constructs a new reactor reading events from `source`, which may be a reference to or [Arc] of an event store.
+    pub fn new(source: S) -> Self {
+        MemoryReactor {
+            source,
+            stop_count: Mutex::default(),
+            stop_signal: Condvar::new(),
+            positions: Mutex::default(),
+        }
+    }
+
+    /// Gets the position of the last event reacted to by the named reaction.
+    pub fn position(&self, reaction_name: &str) -> Since {
+        self.positions
+            .lock()
+            .get(reaction_name)
+            .cloned()
+            .unwrap_or(Since::BeginningOfStream)
+    }
+
+    /// Feeds the reaction any events appended since it last reacted, returning the number of events reacted to.
+    ///
+    /// Unlike [Reactor::start_reaction], this returns as soon as the reaction has caught up, so tests can drive
+    /// reactions synchronously.
+    ///
+    /// Returns [ReactionError::UnsupportedPredicate] if the reaction's predicate has any metadata predicates, which
+    /// cannot be matched against the raw events read from a [GlobalEventSource].
+    pub fn react_pending<R: Reaction>(
+        &self,
+        reaction: &mut R,
+    ) -> Result<usize, ReactionError<S::Error, R::Error>> {
+        let mut event_count = 0;
+
+        let predicate = RawEventPredicate::new(reaction.predicate())
+            .map_err(ReactionError::UnsupportedPredicate)?;
+        let since = self.position(R::reaction_name());
+        let events = self
+            .source
+            .read_all_events(since, None)
+            .map_err(ReactionError::Reactor)?;
+
+        for event in events {
+            let event_id = event.event_id;
+            if predicate.matches(&event) {
+                reaction.react(event).map_err(ReactionError::React)?;
+                event_count += 1;
+            }
+
+            self.positions
+                .lock()
+                .insert(R::reaction_name(), Since::Event(event_id));
+        }
+
+        Ok(event_count)
+    }
+
+    /// Sleeps for `duration`, or until signalled to stop a reaction started when the stop count was `started`.
+    fn sleep(&self, duration: Duration, started: u64) {
+        let deadline = Instant::now() + duration;
+        let mut stops = self.stop_count.lock();
+        while *stops == started {
+            if self
+                .stop_signal
+                .wait_until(&mut stops, deadline)
+                .timed_out()
+            {
+                break;
+            }
+        }
+    }
+}
+
+impl<S> Reactor for MemoryReactor<S>
+where
+    S: GlobalEventSource,
+{
+    type Error = S::Error;
+
+    fn start_reaction<R: Reaction>(
+        &self,
+        mut reaction: R,
+    ) -> Result<usize, ReactionError<Self::Error, R::Error>> {
+        let mut event_count = 0;
+        let started = *self.stop_count.lock();
+
+        while *self.stop_count.lock() == started {
+            event_count += self.react_pending(&mut reaction)?;
+            self.sleep(R::interval(), started);
+        }
+
+        Ok(event_count)
+    }
+
+    fn stop_reaction(&self) {
+        let mut stop_count = self.stop_count.lock();
+        *stop_count = stop_count.wrapping_add(1);
+        self.stop_signal.notify_all();
     }
 }
 
@@ -1,5 +1,11 @@
 use super::*;
 use crate::testing::*;
+use cqrs_core::{
+    projection::{ProjectedEvent, Projection, Projector},
+    reactor::{
+        AggregatePredicate, EventTypesPredicate, MetadataPredicatesUnsupported, ReactionPredicate,
+    },
+};
 
 type TestMemoryEventStore = EventStore<TestAggregate, TestEvent, TestMetadata>;
 
@@ -64,3 +70,496 @@ fn can_get_different_event_streams() {
     let events2 = es.read_events(&TestId("other"), Since::BeginningOfStream, None);
     assert_ne!(events1, events2);
 }
+
+#[test]
+fn can_append_to_an_existing_stream_with_expected_version() {
+    let es = TestMemoryEventStore::default();
+    let id = TestId("");
+    es.append_events(&id, &vec![TestEvent], Some(Precondition::New), TestMetadata)
+        .unwrap();
+    let first_sequence = es
+        .append_events(
+            &id,
+            &vec![TestEvent],
+            Some(Precondition::ExpectedVersion(Version::new(1))),
+            TestMetadata,
+        )
+        .unwrap();
+    assert_eq!(first_sequence, EventNumber::new(2).unwrap());
+}
+
+#[test]
+fn appending_to_an_existing_stream_checks_its_current_version() {
+    let es = TestMemoryEventStore::default();
+    let id = TestId("");
+    es.append_events(&id, &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+
+    let precondition = Precondition::ExpectedVersion(Version::new(2));
+    assert_eq!(
+        PreconditionFailed(precondition),
+        es.append_events(&id, &vec![TestEvent], Some(precondition), TestMetadata)
+            .unwrap_err()
+    );
+
+    let events = es.read_events(&id, Since::BeginningOfStream, None).unwrap();
+    assert_eq!(1, events.unwrap().len());
+}
+
+#[test]
+fn can_read_all_events_in_global_order() {
+    let es = TestMemoryEventStore::default();
+    es.append_events(&TestId("a"), &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+    es.append_events(&TestId("b"), &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+    es.append_events(&TestId("a"), &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+
+    let events = es.read_all_events(Since::BeginningOfStream, None).unwrap();
+    let positions: Vec<_> = events
+        .iter()
+        .map(|e| (e.event_id.get(), e.entity_id.as_str(), e.sequence.get()))
+        .collect();
+    assert_eq!(positions, vec![(1, "a", 1), (2, "b", 1), (3, "a", 2)]);
+    assert!(events.iter().all(|e| e.aggregate_type == "test"));
+}
+
+#[test]
+fn can_read_all_events_since_a_global_position_with_max_count() {
+    let es = TestMemoryEventStore::default();
+    for id in &["a", "b", "c", "d"] {
+        es.append_events(&TestId(id), &vec![TestEvent], None, TestMetadata)
+            .unwrap();
+    }
+
+    let events = es
+        .read_all_events(Since::Event(EventNumber::new(1).unwrap()), Some(2))
+        .unwrap();
+    let ids: Vec<_> = events.iter().map(|e| e.event_id.get()).collect();
+    assert_eq!(ids, vec![2, 3]);
+}
+
+#[test]
+fn can_read_events_with_the_metadata_they_were_appended_with() {
+    let es: EventStore<TestAggregate, TestEvent, &'static str> = EventStore::default();
+    let id = TestId("");
+    es.append_events(&id, &vec![TestEvent, TestEvent], None, "first")
+        .unwrap();
+    es.append_events(&id, &vec![TestEvent], None, "second")
+        .unwrap();
+
+    let events = es
+        .read_events_with_metadata(&id, Since::Event(EventNumber::MIN_VALUE), Some(2))
+        .unwrap()
+        .unwrap();
+    let metadata: Vec<_> = events
+        .iter()
+        .map(|e| (e.sequence.get(), e.metadata))
+        .collect();
+    assert_eq!(metadata, vec![(2, "first"), (3, "second")]);
+}
+
+#[test]
+fn reading_events_with_metadata_for_missing_stream_returns_none() {
+    let es = TestMemoryEventStore::default();
+    let events = es
+        .read_events_with_metadata(&TestId("missing"), Since::BeginningOfStream, None)
+        .unwrap();
+    assert_eq!(events, None);
+}
+
+#[test]
+fn can_read_an_event_stream_in_reverse() {
+    let es = TestMemoryEventStore::default();
+    let id = TestId("");
+    es.append_events(
+        &id,
+        &vec![TestEvent, TestEvent, TestEvent],
+        None,
+        TestMetadata,
+    )
+    .unwrap();
+
+    let events = es
+        .read_events_reverse(&id, Before::EndOfStream, None)
+        .unwrap()
+        .unwrap();
+    let sequences: Vec<_> = events.iter().map(|e| e.sequence.get()).collect();
+    assert_eq!(sequences, vec![3, 2, 1]);
+}
+
+#[test]
+fn can_read_an_event_stream_in_reverse_before_an_event_with_max_count() {
+    let es = TestMemoryEventStore::default();
+    let id = TestId("");
+    es.append_events(
+        &id,
+        &vec![TestEvent, TestEvent, TestEvent],
+        None,
+        TestMetadata,
+    )
+    .unwrap();
+
+    let events = es
+        .read_events_reverse(&id, Before::Event(EventNumber::new(3).unwrap()), Some(1))
+        .unwrap()
+        .unwrap();
+    let sequences: Vec<_> = events.iter().map(|e| e.sequence.get()).collect();
+    assert_eq!(sequences, vec![2]);
+}
+
+#[test]
+fn can_commit_a_unit_of_work_across_entities() {
+    let es = TestMemoryEventStore::default();
+    es.append_events(&TestId("a"), &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+
+    let mut unit_of_work = UnitOfWork::new();
+    unit_of_work
+        .append(
+            &TestId("a"),
+            vec![TestEvent],
+            Some(Precondition::ExpectedVersion(Version::new(1))),
+            TestMetadata,
+        )
+        .append(
+            &TestId("b"),
+            vec![TestEvent, TestEvent],
+            Some(Precondition::New),
+            TestMetadata,
+        )
+        .append(
+            &TestId("b"),
+            vec![TestEvent],
+            Some(Precondition::ExpectedVersion(Version::new(2))),
+            TestMetadata,
+        );
+
+    let first_sequences = es.commit(unit_of_work).unwrap();
+    let first_sequences: Vec<_> = first_sequences.iter().map(|s| s.get()).collect();
+    assert_eq!(first_sequences, vec![2, 1, 3]);
+
+    let events = es.read_all_events(Since::BeginningOfStream, None).unwrap();
+    assert_eq!(events.len(), 5);
+}
+
+#[test]
+fn unit_of_work_with_failed_precondition_commits_nothing() {
+    let es = TestMemoryEventStore::default();
+    es.append_events(&TestId("b"), &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+
+    let mut unit_of_work = UnitOfWork::new();
+    unit_of_work
+        .append(
+            &TestId("a"),
+            vec![TestEvent],
+            Some(Precondition::New),
+            TestMetadata,
+        )
+        .append(
+            &TestId("b"),
+            vec![TestEvent],
+            Some(Precondition::New),
+            TestMetadata,
+        );
+
+    let err = es.commit(unit_of_work).unwrap_err();
+    assert_eq!(
+        err,
+        EntityPreconditionFailed {
+            entity_id: String::from("b"),
+            precondition: Precondition::New,
+        }
+    );
+
+    let events = es
+        .read_events(&TestId("a"), Since::BeginningOfStream, None)
+        .unwrap();
+    assert_eq!(events, None);
+}
+
+#[derive(Debug, Default)]
+struct CountingReaction {
+    entity_ids: Arc<Mutex<Vec<String>>>,
+}
+
+impl Reaction for CountingReaction {
+    type Error = Void;
+
+    fn reaction_name() -> &'static str {
+        "counting"
+    }
+
+    fn react(&mut self, event: RawEvent) -> Result<(), Self::Error> {
+        self.entity_ids.lock().push(event.entity_id);
+        Ok(())
+    }
+
+    fn predicate(&self) -> ReactionPredicate {
+        ReactionPredicate::default()
+    }
+
+    fn interval() -> Duration {
+        Duration::from_millis(10)
+    }
+}
+
+#[test]
+fn memory_reactor_feeds_events_to_reaction_and_resumes_from_its_position() {
+    let es = Arc::new(TestMemoryEventStore::default());
+    es.append_events(&TestId("a"), &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+    es.append_events(&TestId("b"), &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+
+    let reactor = Arc::new(MemoryReactor::new(Arc::clone(&es)));
+    let entity_ids = Arc::default();
+    let reaction = CountingReaction {
+        entity_ids: Arc::clone(&entity_ids),
+    };
+
+    let thread_reactor = Arc::clone(&reactor);
+    let handle = std::thread::spawn(move || thread_reactor.start_reaction(reaction));
+    std::thread::sleep(Duration::from_millis(50));
+    reactor.stop_reaction();
+
+    assert_eq!(2, handle.join().unwrap().unwrap());
+    assert_eq!(vec!["a", "b"], *entity_ids.lock());
+    assert_eq!(
+        Since::Event(EventNumber::new(2).unwrap()),
+        reactor.position("counting")
+    );
+}
+
+#[test]
+fn memory_reactor_can_restart_a_stopped_reaction() {
+    fn react_until(
+        reactor: &Arc<MemoryReactor<Arc<TestMemoryEventStore>>>,
+        entity_ids: &Arc<Mutex<Vec<String>>>,
+        count: usize,
+    ) -> usize {
+        let thread_reactor = Arc::clone(reactor);
+        let reaction = CountingReaction {
+            entity_ids: Arc::clone(entity_ids),
+        };
+        let handle = std::thread::spawn(move || thread_reactor.start_reaction(reaction));
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while entity_ids.lock().len() < count {
+            assert!(
+                Instant::now() < deadline,
+                "reaction did not catch up in time"
+            );
+            std::thread::yield_now();
+        }
+        reactor.stop_reaction();
+
+        handle.join().unwrap().unwrap()
+    }
+
+    let es = Arc::new(TestMemoryEventStore::default());
+    let reactor = Arc::new(MemoryReactor::new(Arc::clone(&es)));
+    let entity_ids = Arc::default();
+
+    es.append_events(&TestId("a"), &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+    assert_eq!(1, react_until(&reactor, &entity_ids, 1));
+
+    es.append_events(&TestId("b"), &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+    assert_eq!(1, react_until(&reactor, &entity_ids, 2));
+
+    assert_eq!(vec!["a", "b"], *entity_ids.lock());
+    assert_eq!(
+        Since::Event(EventNumber::new(2).unwrap()),
+        reactor.position("counting")
+    );
+}
+
+#[test]
+fn memory_reactor_stop_is_not_undone_by_a_later_start() {
+    let es = Arc::new(TestMemoryEventStore::default());
+    let reactor = Arc::new(MemoryReactor::new(Arc::clone(&es)));
+    let entity_ids = Arc::new(Mutex::new(Vec::new()));
+
+    es.append_events(&TestId("a"), &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+
+    let start = |entity_ids: &Arc<Mutex<Vec<String>>>| {
+        let thread_reactor = Arc::clone(&reactor);
+        let reaction = CountingReaction {
+            entity_ids: Arc::clone(entity_ids),
+        };
+        std::thread::spawn(move || thread_reactor.start_reaction(reaction))
+    };
+
+    let stopped = start(&entity_ids);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while entity_ids.lock().is_empty() {
+        assert!(
+            Instant::now() < deadline,
+            "reaction did not catch up in time"
+        );
+        std::thread::yield_now();
+    }
+
+    reactor.stop_reaction();
+    let restarted = start(&entity_ids);
+
+    assert_eq!(1, stopped.join().unwrap().unwrap());
+
+    reactor.stop_reaction();
+
+    assert_eq!(0, restarted.join().unwrap().unwrap());
+}
+
+#[test]
+fn subscription_rejects_metadata_predicates() {
+    let es = TestMemoryEventStore::default();
+
+    assert_eq!(
+        MetadataPredicatesUnsupported,
+        es.subscribe(ReactionPredicate::default().with_metadata("initiated_by", "admin"))
+            .unwrap_err()
+    );
+}
+
+struct AdminReaction;
+
+impl Reaction for AdminReaction {
+    type Error = Void;
+
+    fn reaction_name() -> &'static str {
+        "admin"
+    }
+
+    fn react(&mut self, _event: RawEvent) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn predicate(&self) -> ReactionPredicate {
+        ReactionPredicate::default().with_metadata("initiated_by", "admin")
+    }
+
+    fn interval() -> Duration {
+        Duration::from_millis(10)
+    }
+}
+
+#[test]
+fn memory_reactor_rejects_metadata_predicates() {
+    let es = TestMemoryEventStore::default();
+    let reactor = MemoryReactor::new(&es);
+
+    es.append_events(&TestId("a"), &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+
+    assert_eq!(
+        Err(ReactionError::UnsupportedPredicate(
+            MetadataPredicatesUnsupported
+        )),
+        reactor.start_reaction(AdminReaction)
+    );
+    assert_eq!(Since::BeginningOfStream, reactor.position("admin"));
+}
+
+#[test]
+fn memory_reactor_can_react_to_pending_events_synchronously() {
+    let es = TestMemoryEventStore::default();
+    let reactor = MemoryReactor::new(&es);
+    let mut reaction = CountingReaction::default();
+
+    es.append_events(&TestId("a"), &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+    assert_eq!(1, reactor.react_pending(&mut reaction).unwrap());
+
+    es.append_events(
+        &TestId("b"),
+        &vec![TestEvent, TestEvent],
+        None,
+        TestMetadata,
+    )
+    .unwrap();
+    assert_eq!(2, reactor.react_pending(&mut reaction).unwrap());
+    assert_eq!(0, reactor.react_pending(&mut reaction).unwrap());
+
+    assert_eq!(vec!["a", "b", "b"], *reaction.entity_ids.lock());
+}
+
+#[test]
+fn subscription_returns_matching_events_appended_since_last_poll() {
+    let es = TestMemoryEventStore::default();
+    let mut all = es.subscribe(ReactionPredicate::default()).unwrap();
+    let mut none = es
+        .subscribe(ReactionPredicate::new(AggregatePredicate::AllAggregates(
+            EventTypesPredicate::specific(&["other"]),
+        )))
+        .unwrap();
+
+    es.append_events(&TestId("a"), &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+    es.append_events(&TestId("b"), &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+
+    let ids: Vec<_> = all
+        .poll()
+        .unwrap()
+        .iter()
+        .map(|e| e.event_id.get())
+        .collect();
+    assert_eq!(ids, vec![1, 2]);
+    assert!(all.poll().unwrap().is_empty());
+    assert!(none.poll().unwrap().is_empty());
+    assert_eq!(all.position(), none.position());
+
+    es.append_events(&TestId("a"), &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+
+    let ids: Vec<_> = all
+        .poll()
+        .unwrap()
+        .iter()
+        .map(|e| e.event_id.get())
+        .collect();
+    assert_eq!(ids, vec![3]);
+}
+
+#[derive(Debug, Default)]
+struct EntityIdProjection {
+    entity_ids: Vec<String>,
+}
+
+impl Projection for EntityIdProjection {
+    type Aggregate = TestAggregate;
+    type Error = Void;
+    type Event = TestEvent;
+
+    fn project(&mut self, event: ProjectedEvent<Self::Event>) -> Result<(), Self::Error> {
+        self.entity_ids.push(event.entity_id);
+        Ok(())
+    }
+}
+
+#[test]
+fn projector_catches_up_with_the_event_store() {
+    let es = TestMemoryEventStore::default();
+    let mut projector = Projector::new(EntityIdProjection::default());
+
+    es.append_events(&TestId("a"), &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+    es.append_events(&TestId("b"), &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+    assert_eq!(2, projector.catch_up(&es).unwrap());
+
+    es.append_events(&TestId("a"), &vec![TestEvent], None, TestMetadata)
+        .unwrap();
+    assert_eq!(1, projector.catch_up(&es).unwrap());
+
+    assert_eq!(
+        Since::Event(EventNumber::new(3).unwrap()),
+        projector.position()
+    );
+    assert_eq!(vec!["a", "b", "a"], projector.projection().entity_ids);
+}
@@ -1,4 +1,7 @@
-use cqrs_core::{Aggregate, AggregateCommand, AggregateEvent, AggregateId, Event};
+use cqrs_core::{
+    Aggregate, AggregateCommand, AggregateEvent, AggregateId, DeserializableEvent, Event,
+    SerializableEvent,
+};
 use void::Void;
 
 /// A test aggregate with no state
@@ -54,3 +57,58 @@ impl Event for TestEvent {
 impl AggregateEvent<TestAggregate> for TestEvent {
     fn apply_to(self, _aggregate: &mut TestAggregate) {}
 }
+
+impl SerializableEvent for TestEvent {
+    type Error = Void;
+
+    fn serialize_event_to_buffer(&self, _buffer: &mut Vec<u8>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl DeserializableEvent for TestEvent {
+    type Error = Void;
+
+    fn deserialize_event_from_buffer(
+        _data: &[u8],
+        event_type: &str,
+    ) -> Result<Option<Self>, Self::Error> {
+        Ok(if event_type == EVENT_TYPE {
+            Some(TestEvent)
+        } else {
+            None
+        })
+    }
+}
+
+/// Drives a future to completion on the current thread, parking the thread while the future is pending.
+#[cfg(feature = "async")]
+pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    use std::{
+        sync::Arc,
+        task::{Context, Poll, Wake, Waker},
+        thread::{self, Thread},
+    };
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut context = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+        thread::park();
+    }
+}
@@ -1,8 +1,9 @@
 //! Provides a trivial implementation of event/snapshot/entity source/sink/store constructs.
 
 use cqrs_core::{
-    Aggregate, AggregateEvent, AggregateId, EventNumber, EventSink, EventSource, Precondition,
-    Since, SnapshotSink, SnapshotSource, Version, VersionedAggregate, VersionedEvent,
+    Aggregate, AggregateEvent, AggregateId, Before, EventNumber, EventSink, EventSource,
+    Precondition, ReverseEventSource, Since, SnapshotSink, SnapshotSource, Version,
+    VersionedAggregate, VersionedEvent,
 };
 use std::{fmt, iter::Empty, marker::PhantomData};
 use void::Void;
@@ -10,7 +11,7 @@ use void::Void;
 /// A trivial store that never has any events, and which always succeeds in
 /// persisting data (which is immediately dropped).
 #[derive(Clone, Copy)]
-pub struct NullEventStore<A, E>(PhantomData<*const (A, E)>)
+pub struct NullEventStore<A, E>(PhantomData<fn(A, E)>)
 where
     A: Aggregate,
     E: AggregateEvent<A>;
@@ -67,6 +68,25 @@ where
     }
 }
 
+impl<A, E> ReverseEventSource<A, E> for NullEventStore<A, E>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+{
+    #[inline]
+    fn read_events_reverse<I>(
+        &self,
+        _id: &I,
+        _before: Before,
+        _max_count: Option<u64>,
+    ) -> Result<Option<Self::Events>, Self::Error>
+    where
+        I: AggregateId<A>,
+    {
+        Ok(None)
+    }
+}
+
 impl<A, E, M> EventSink<A, E, M> for NullEventStore<A, E>
 where
     A: Aggregate,
@@ -92,7 +112,7 @@ where
 /// A trivial store that never has any snapshots, and which always succeeds in
 /// persisting data (which is immediately dropped).
 #[derive(Clone, Copy)]
-pub struct NullSnapshotStore<A>(PhantomData<*const A>)
+pub struct NullSnapshotStore<A>(PhantomData<fn(A)>)
 where
     A: Aggregate;
 